@@ -5,15 +5,20 @@ mod unsafe_dataflow;
 mod overflow;
 mod err_handling;
 mod truncation;
+mod mono_layout;
 
-use rustc_hir::{ItemKind, ImplPolarity, ItemId, hir_id::OwnerId, OwnerNode};
+use rustc_hir::{def_id::DefId, hir_id::OwnerId, OwnerNode};
 use rustc_middle::hir::Owner;
-use rustc_middle::ty::{self, Ty, ParamEnv, TypeAndMut, TyKind, TyCtxt, IntTy, UintTy, FloatTy, TraitPredicate, Binder};
+use rustc_middle::ty::layout::TyAndLayout;
+use rustc_middle::ty::{self, Ty, ParamEnv, TypeAndMut, TyKind, TyCtxt, IntTy, UintTy, FloatTy, TraitPredicate, Binder, TypeVisitableExt};
 
 use snafu::{Error, ErrorCompat};
 
 use crate::report::ReportLevel;
 use crate::context::RuMorphCtxt;
+use crate::target::layout_on;
+use crate::marker::{GenericImpl, Guarantee};
+use crate::validity::{self, Restriction};
 use crate::progress_info;
 
 use std::collections::HashSet;
@@ -25,6 +30,7 @@ pub use unsafe_dataflow::{BehaviorFlag as UnsafeDataflowBehaviorFlag, UnsafeData
 pub use overflow::{BehaviorFlag as OverflowBehaviorFlag, OverflowChecker};
 pub use err_handling::{BehaviorFlag as ErrHandleBehaviorFlag, ErrHandleChecker};
 pub use truncation::{BehaviorFlag as TruncationBehaviorFlag, TruncationChecker};
+pub use mono_layout::{BehaviorFlag as MonoLayoutBehaviorFlag, MonoLayoutChecker};
 
 pub type AnalysisResult<'tcx, T> = Result<T, Box<dyn AnalysisError + 'tcx>>;
 
@@ -85,6 +91,7 @@ pub enum AnalysisKind {
     Overflow(OverflowBehaviorFlag),
     ErrHandle(ErrHandleBehaviorFlag),
     Truncation(TruncationBehaviorFlag),
+    MonoLayout(MonoLayoutBehaviorFlag),
 }
 
 trait IntoReportLevel {
@@ -123,6 +130,9 @@ impl Into<Cow<'static, str>> for AnalysisKind {
                 // if bypass_kinds.contains(BrokenLayoutBehaviorFlag::VEC_SET_LEN) {
                 //     v.push("VecSetLen")
                 // }
+                if bypass_kinds.contains(BrokenLayoutBehaviorFlag::UNKNOWN_LAYOUT) {
+                    v.push("UnknownLayout")
+                }
                 v.join("/").into()
             },
             AnalysisKind::UninitExposure(bypass_kinds) => {
@@ -176,6 +186,19 @@ impl Into<Cow<'static, str>> for AnalysisKind {
                 let mut v = vec!["Truncation:"];
                 v.join("/").into()
             },
+            AnalysisKind::MonoLayout(bypass_kinds) => {
+                let mut v = vec!["MonoLayout:"];
+                if bypass_kinds.contains(MonoLayoutBehaviorFlag::MISALIGNED) {
+                    v.push("Misaligned")
+                }
+                if bypass_kinds.contains(MonoLayoutBehaviorFlag::INVALID_VALUE) {
+                    v.push("InvalidValue")
+                }
+                if bypass_kinds.contains(MonoLayoutBehaviorFlag::UNKNOWN_LAYOUT) {
+                    v.push("UnknownLayout")
+                }
+                v.join("/").into()
+            },
         }
     }
 }
@@ -193,10 +216,12 @@ pub enum Comparison {
     Noidea,
     NoideaG,
     NoideaL,
+    // the layout of a non-generic side can't be computed, even after normalization
+    Unknown,
 }
 
-pub struct LayoutChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct LayoutChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
     from_ty: Ty<'tcx>,
     to_ty: Ty<'tcx>,
     fty_layout: bool,
@@ -204,13 +229,22 @@ pub struct LayoutChecker<'tcx> {
     align_status: Comparison,
     size_status: Comparison,
     ty_bnd: HashSet<Ty<'tcx>>,
+    /// layout targets on which the pointee of from_ty is less aligned than the one of to_ty
+    misaligned_on: Vec<String>,
+    /// layout targets on which the pointees have different sizes
+    size_mismatch_on: Vec<String>,
+    /// non-generic pointees whose layout can't be computed
+    unknown_layouts: Vec<Ty<'tcx>>,
+    /// guarantees of the marker traits bounding the pointees, see `crate::marker`
+    from_guarantee: Guarantee,
+    to_guarantee: Guarantee,
 }
 
 // LayoutChecker can help us get the align/size status of type conversion
-// take one exception case for target into consideration
-// on x86 u64 and f64 are only aligned to 4 bytes
-impl<'tcx> LayoutChecker<'tcx> {
-    pub fn new(rc: RuMorphCtxt<'tcx>, p_env: ParamEnv<'tcx>, f_ty: Ty<'tcx>, t_ty: Ty<'tcx>) -> Self {
+// besides the host, the layout targets of the context are considered,
+// e.g. on i686 u64 and f64 are only aligned to 4 bytes
+impl<'a, 'tcx> LayoutChecker<'a, 'tcx> {
+    pub fn new(rc: RuMorphCtxt<'a, 'tcx>, p_env: ParamEnv<'tcx>, f_ty: Ty<'tcx>, t_ty: Ty<'tcx>) -> Self {
         // optimization option to call trait checker
         let opt_option = rc.opt_option();
 
@@ -219,8 +253,30 @@ impl<'tcx> LayoutChecker<'tcx> {
         // rustc_middle::ty::TyCtxt
         let tcx = rc.tcx();
         let (f_ty_, t_ty_) = (get_pointee(f_ty), get_pointee(t_ty));
+
+        let (mut misaligned_on, mut size_mismatch_on) = (Vec::new(), Vec::new());
+        // size comparison on the layout targets where it differs from the host, Less wins
+        let mut target_size_status = None;
+        for target in rc.layout_targets() {
+            if let Some(from) = layout_on(tcx, p_env, f_ty_, &target.data_layout)
+                && let Some(to) = layout_on(tcx, p_env, t_ty_, &target.data_layout)
+            {
+                if from.align < to.align {
+                    misaligned_on.push(target.name.clone());
+                }
+                if from.size != to.size {
+                    size_mismatch_on.push(target.name.clone());
+                    if from.size < to.size {
+                        target_size_status = Some(Comparison::Less);
+                    } else if target_size_status.is_none() {
+                        target_size_status = Some(Comparison::Greater);
+                    }
+                }
+            }
+        }
         
-        let ty_bnd = if opt_option == true {
+        // concrete types are compared directly, e.g. for each satisfied type below
+        let ty_bnd = if opt_option == true && (f_ty_.has_param() || t_ty_.has_param()) {
             let tc = GenericChecker::new(rc, p_env, f_ty_, t_ty_);
             tc.get_satisfied_ty()
         } else {
@@ -228,29 +284,25 @@ impl<'tcx> LayoutChecker<'tcx> {
             empty_ty_set
         };
 
-        // try to handle external type if we can't get type information
-        let mut ext_fty_info: u64 = 0;
-        let mut ext_tty_info: u64 = 0;
-        if let Err(_) = tcx.layout_of(p_env.and(f_ty_)) {
-            match get_external(tcx, f_ty_) {
-                Some(external_ty) => {
-                    ext_fty_info = external_ty;
-                },
-                None => {},
-            }
+        let (from_layout, to_layout) = (
+            layout_of_normalized(tcx, p_env, f_ty_),
+            layout_of_normalized(tcx, p_env, t_ty_),
+        );
+        let is_generic = |ty: Ty<'tcx>| matches!(ty.kind(), TyKind::Param(_));
+        let from_guarantee = rc.marker_traits().guarantees_of(tcx, p_env, f_ty_);
+        let to_guarantee = rc.marker_traits().guarantees_of(tcx, p_env, t_ty_);
+        let mut unknown_layouts = Vec::new();
+        if from_layout.is_none() && !is_generic(f_ty_) {
+            unknown_layouts.push(f_ty_);
         }
-        if let Err(_) = tcx.layout_of(p_env.and(t_ty_)) {
-            match get_external(tcx, t_ty_) {
-                Some(external_ty) => {
-                    ext_tty_info = external_ty;
-                },
-                None => {},
-            }
+        if to_layout.is_none() && !is_generic(t_ty_) {
+            unknown_layouts.push(t_ty_);
         }
+
         // from_ty_and_layout = rustc_target::abi::TyAndLayout
         // (align_status, size_status)
-        let layout_res = if let Ok(from_ty_and_layout) = tcx.layout_of(p_env.and(f_ty_))
-            && let Ok(to_ty_and_layout) = tcx.layout_of(p_env.and(t_ty_))
+        let layout_res = if let Some(from_ty_and_layout) = from_layout
+            && let Some(to_ty_and_layout) = to_layout
         {
             // in this case, only align_status == Comparison::Less will warn
             f_layout = true;
@@ -269,29 +321,9 @@ impl<'tcx> LayoutChecker<'tcx> {
             } else {
                 Comparison::Noidea
             };
-            // exception cases for u64 and f64
-            match ag_status {
-                Comparison::Less | Comparison::Equal => {},
-                _ => {
-                    // check type kind of from_ty
-                    match f_ty_.kind() {
-                        TyKind::Uint(uint_ty) => {
-                            // u64
-                            if (uint_ty.name_str() == "u64") && (4 < to_align.abi.bytes()) {
-                                // progress_info!("from_align could be :{} on x86", 4);
-                                ag_status = Comparison::Less;
-                            }
-                        },
-                        TyKind::Float(float_ty) => {
-                            // f64
-                            if (float_ty.name_str() == "f64") && (4 < to_align.abi.bytes()) {
-                                // progress_info!("from_align could be :{} on x86", 4);
-                                ag_status = Comparison::Less;
-                            }
-                        },
-                        _ => {},
-                    }
-                },
+            // aligned on the host, but not on every layout target
+            if !misaligned_on.is_empty() {
+                ag_status = Comparison::Less;
             }
             // if to_ty is usize, then take the case away
             // or from_ty is c_void, also take the case away
@@ -301,7 +333,7 @@ impl<'tcx> LayoutChecker<'tcx> {
             }
             // progress_info!("LayoutChecker- from_size:{}, to_size:{}", from_size.bytes(), to_size.bytes());
             // for size_status
-            let sz_status = if from_size.bytes() < to_size.bytes() {
+            let mut sz_status = if from_size.bytes() < to_size.bytes() {
                 Comparison::Less
             } else if from_size.bytes() == to_size.bytes() {
                 Comparison::Equal
@@ -310,9 +342,15 @@ impl<'tcx> LayoutChecker<'tcx> {
             } else {
                 Comparison::Noidea
             };
+            // same size on the host, but not on every layout target
+            match target_size_status {
+                Some(Comparison::Less) => sz_status = Comparison::Less,
+                Some(status) if matches!(sz_status, Comparison::Equal) => sz_status = status,
+                _ => {},
+            }
 
             (ag_status, sz_status)
-        } else if let Ok(from_ty_and_layout) = tcx.layout_of(p_env.and(f_ty_)) {
+        } else if let Some(from_ty_and_layout) = from_layout {
             f_layout = true;
             // we can only identify from_ty's layout
             let from_layout = from_ty_and_layout.layout;
@@ -324,7 +362,14 @@ impl<'tcx> LayoutChecker<'tcx> {
                 // in this case, we can't get layout because t_ty_ is generic type
                 // call GenericChecker for help
                 let is_wrapped = f_ty_.contains(t_ty_);
-                if !is_wrapped {
+                if to_guarantee.contains(Guarantee::UNALIGNED) {
+                    // every implementor is aligned to 1 byte
+                    if from_align.abi.bytes() == 1 {
+                        Comparison::Equal
+                    } else {
+                        Comparison::Greater
+                    }
+                } else if !is_wrapped {
                     if ty_bnd.len() == 0 {
                         // t_ty_ could be arbitrary types
                         Comparison::Less
@@ -347,20 +392,7 @@ impl<'tcx> LayoutChecker<'tcx> {
                     Comparison::Equal
                 }
             } else {
-                // we can't get layout because t_ty_ might be external type
-                // apply heuristics
-                let mut res = Comparison::Noidea;
-                if ext_tty_info != 0 {
-                    // we have some type info of to_ty
-                    if from_align.abi.bytes() < ext_tty_info {
-                        res = Comparison::Less;
-                    } else if from_align.abi.bytes() == ext_tty_info {
-                        res = Comparison::Equal;
-                    } else {
-                        res = Comparison::Greater;
-                    }
-                }
-                res
+                Comparison::Unknown
             };
 
             if f_ty_.is_c_void(tcx) || f_ty_.to_string() == "usize" || from_size.bytes() == 0 {
@@ -374,7 +406,7 @@ impl<'tcx> LayoutChecker<'tcx> {
             };
 
             (ag_status, sz_status)
-        } else if let Ok(to_ty_and_layout) = tcx.layout_of(p_env.and(t_ty_)) {
+        } else if let Some(to_ty_and_layout) = to_layout {
             t_layout = true;
             // we can only identify to_ty's layout
             let to_layout = to_ty_and_layout.layout;
@@ -385,7 +417,14 @@ impl<'tcx> LayoutChecker<'tcx> {
                 // f_ty_ is generic type
                 // call GenericChecker for help
                 let is_wrapped = t_ty_.contains(f_ty_);
-                if !is_wrapped {
+                if from_guarantee.contains(Guarantee::UNALIGNED) {
+                    // every implementor is aligned to 1 byte
+                    if to_align.abi.bytes() == 1 {
+                        Comparison::Equal
+                    } else {
+                        Comparison::Less
+                    }
+                } else if !is_wrapped {
                     if ty_bnd.len() == 0 {
                         // f_ty_ could be arbitrary types
                         if to_align.abi.bytes() == 1 {
@@ -412,19 +451,7 @@ impl<'tcx> LayoutChecker<'tcx> {
                     Comparison::Equal
                 }
             } else {
-                // we can't identify f_ty layout not because genric type
-                // try applying heuristics
-                let mut res = Comparison::Noidea;
-                if ext_fty_info != 0 {
-                    if ext_fty_info < to_align.abi.bytes() {
-                        res = Comparison::Less;
-                    } else if ext_fty_info == to_align.abi.bytes() {
-                        res = Comparison::Equal;
-                    } else {
-                        res = Comparison::Greater;
-                    }
-                }
-                res
+                Comparison::Unknown
             };
             
 
@@ -439,6 +466,8 @@ impl<'tcx> LayoutChecker<'tcx> {
             };
 
             (ag_status, sz_status)
+        } else if !unknown_layouts.is_empty() {
+            (Comparison::Unknown, Comparison::Unknown)
         } else {
             (Comparison::Noidea, Comparison::Noidea)
         };
@@ -451,9 +480,59 @@ impl<'tcx> LayoutChecker<'tcx> {
             align_status: layout_res.0,
             size_status: layout_res.1,
             ty_bnd: ty_bnd.clone(),
+            misaligned_on,
+            size_mismatch_on,
+            unknown_layouts,
+            from_guarantee,
+            to_guarantee,
         }
     }
 
+    /// Layout targets on which the conversion is misaligned, e.g. `["i686", "armv7"]`
+    pub fn misaligned_targets(&self) -> &[String] {
+        &self.misaligned_on
+    }
+
+    /// Layout targets on which the pointees differ in size
+    pub fn size_mismatch_targets(&self) -> &[String] {
+        &self.size_mismatch_on
+    }
+
+    /// Guarantees of the marker traits bounding the pointee of from_ty
+    pub fn from_guarantee(&self) -> Guarantee {
+        self.from_guarantee
+    }
+
+    /// Guarantees of the marker traits bounding the pointee of to_ty
+    pub fn to_guarantee(&self) -> Guarantee {
+        self.to_guarantee
+    }
+
+    /// Non-generic pointees whose layout can't be computed
+    pub fn unknown_layouts(&self) -> &[Ty<'tcx>] {
+        &self.unknown_layouts
+    }
+
+    /// e.g. " (misaligned on i686, armv7; sizes differ on wasm32)", or empty
+    pub fn target_note(&self) -> String {
+        join_notes(&self.target_notes())
+    }
+
+    /// e.g. `["misaligned on i686, armv7", "sizes differ on wasm32"]`
+    pub fn target_notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+        for ty in &self.unknown_layouts {
+            notes.push(format!("layout of `{}` unknown", ty));
+        }
+        if !self.misaligned_on.is_empty() {
+            notes.push(format!("misaligned on {}", self.misaligned_on.join(", ")));
+        }
+        if !self.size_mismatch_on.is_empty() {
+            notes.push(format!("sizes differ on {}", self.size_mismatch_on.join(", ")));
+        }
+        notes
+    }
+
     pub fn get_align_status(&self) -> Comparison {
         self.align_status
     }
@@ -598,34 +677,56 @@ fn get_pointee(matched_ty: Ty<'_>) -> Ty<'_> {
     pointee
 }
 
-// try to get external type
-// only try to handle primitive type
-fn get_external<'tcx>(tcx: TyCtxt<'tcx>, matched_ty: Ty<'tcx>) -> Option<u64>{
-    let ty_symbol = matched_ty.to_string();
-    if ty_symbol.contains("bool") || ty_symbol.contains("i8") || ty_symbol.contains("u8") {
-        Some(1)
-    } else if ty_symbol.contains("i16") || ty_symbol.contains("u16") {
-        Some(2)
-    } else if ty_symbol.contains("i32") || ty_symbol.contains("u32") || ty_symbol.contains("f32") || ty_symbol.contains("char") {
-        Some(4)
-    } else if ty_symbol.contains("u64") || ty_symbol.contains("i64") || ty_symbol.contains("f64") {
-        Some(8)
-    } else if ty_symbol.contains("u128") || ty_symbol.contains("i128") {
-        Some(16)
+/// Joins the notes of a report, e.g. " (misaligned on i686; sizes differ on wasm32)", or empty
+pub fn join_notes(notes: &[String]) -> String {
+    if notes.is_empty() {
+        String::new()
     } else {
-        None
+        format!(" ({})", notes.join("; "))
+    }
+}
+
+// layout of ty, including external types whose fields are projections or opaque types:
+// external ADTs come from crate metadata, so only the type has to be normalized
+fn layout_of_normalized<'tcx>(tcx: TyCtxt<'tcx>, p_env: ParamEnv<'tcx>, ty: Ty<'tcx>) -> Option<TyAndLayout<'tcx>> {
+    if let Ok(layout) = tcx.layout_of(p_env.and(ty)) {
+        return Some(layout);
     }
+    // reveal opaque types and normalize the remaining projections
+    let p_env = p_env.with_reveal_all_normalized(tcx);
+    let normalized = tcx.try_normalize_erasing_regions(p_env, ty).ok()?;
+    tcx.layout_of(p_env.and(normalized)).ok()
+}
+
+// restricted scalars of ty, see crate::validity; None if its layout is unknown
+fn restrictions_of<'tcx>(tcx: TyCtxt<'tcx>, p_env: ParamEnv<'tcx>, ty: Ty<'tcx>) -> Option<Vec<Restriction<'tcx>>> {
+    layout_of_normalized(tcx, p_env, ty).map(|layout| validity::restrictions(tcx, p_env, layout))
 }
 
-pub struct ValueChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+// restrictions of to_ty that a value of from_ty may violate, see validity::violated;
+// None if either layout is unknown
+fn violated_restrictions<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    p_env: ParamEnv<'tcx>,
+    from_ty: Ty<'tcx>,
+    to_ty: Ty<'tcx>,
+) -> Option<Vec<Restriction<'tcx>>> {
+    let from_restrictions = restrictions_of(tcx, p_env, from_ty)?;
+    let to_restrictions = restrictions_of(tcx, p_env, to_ty)?;
+    Some(validity::violated(&from_restrictions, &to_restrictions))
+}
+
+pub struct ValueChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
     from_ty: Ty<'tcx>,
     to_ty: Ty<'tcx>,
     value_status: Comparison,
+    /// restricted fields of the type the value is read as, if value_status is Less
+    restrictions: Vec<Restriction<'tcx>>,
 }
 
-impl<'tcx> ValueChecker<'tcx> {
-    pub fn new(rc: RuMorphCtxt<'tcx>, p_env: ParamEnv<'tcx>, f_ty: Ty<'tcx>, t_ty: Ty<'tcx>) -> Self {
+impl<'a, 'tcx> ValueChecker<'a, 'tcx> {
+    pub fn new(rc: RuMorphCtxt<'a, 'tcx>, p_env: ParamEnv<'tcx>, f_ty: Ty<'tcx>, t_ty: Ty<'tcx>) -> Self {
         let tcx = rc.tcx();
         let lc = LayoutChecker::new(rc, p_env, f_ty, t_ty);
         let from_ty = lc.get_from_ty();
@@ -635,56 +736,72 @@ impl<'tcx> ValueChecker<'tcx> {
         let (from_gen, to_gen) = lc.is_from_to_generic();
 
         // we only focus on the type conversion between generic and concrete type
-        // compare the range of value set to get the size status
-        // progress_info!("from_ty is c_void? {}", from_ty.is_c_void(tcx));
-        // progress_info!("to_ty is c_void? {}", to_ty.is_c_void(tcx));
-        let is_from_c_void = from_ty.to_string().contains("c_void");
-        let is_to_c_void = to_ty.to_string().contains("c_void");
+        // compare the valid ranges of both sides to get the value status
+        let (is_from_c_void, is_to_c_void) = (from_ty.is_c_void(tcx), to_ty.is_c_void(tcx));
+        let mut restrictions = Vec::new();
         let val_status = if (from_gen == true || is_from_c_void) && (to_gen == false && !is_to_c_void) {
             // generic > concrete
             // is from_ty wrapped in to_ty?
             let is_wrapped = to_ty.contains(from_ty);
-            if !is_wrapped {
-                if ty_bnd.len() == 0 {
-                    // from_ty could be arbitrary type
-                    if to_ty.is_bool() || to_ty.is_str() || to_ty.is_char() || to_ty.is_enum() {
-                        Comparison::Less
+            match restrictions_of(tcx, p_env, to_ty) {
+                // the layout of to_ty can't be computed, so neither can its valid values
+                None => Comparison::Unknown,
+                Some(_) if is_wrapped => Comparison::Equal,
+                Some(to_restrictions) => {
+                    if ty_bnd.len() == 0 {
+                        // from_ty could be arbitrary type
+                        if to_restrictions.is_empty() {
+                            Comparison::Noidea
+                        } else {
+                            restrictions = to_restrictions;
+                            Comparison::Less
+                        }
                     } else {
-                        Comparison::Noidea
-                    }
-                } else {
-                    let mut res = Comparison::Noidea;
-                    for satisfied_ty in ty_bnd {
-                        if (satisfied_ty.is_numeric() || satisfied_ty.is_str() || satisfied_ty.is_char())
-                            && (to_ty.is_bool() || to_ty.is_str() || to_ty.is_char() || to_ty.is_enum()) {
-                            res = Comparison::Less;
+                        let mut res = Comparison::Noidea;
+                        for satisfied_ty in ty_bnd {
+                            if let Some(from_restrictions) = restrictions_of(tcx, p_env, satisfied_ty) {
+                                let violated = validity::violated(&from_restrictions, &to_restrictions);
+                                if !violated.is_empty() {
+                                    res = Comparison::Less;
+                                    restrictions.extend(violated);
+                                }
+                            }
                         }
+                        res
                     }
-                    res
-                }
-            } else {
-                Comparison::Equal
+                },
             }
         } else if from_gen == false && to_gen == true {
             // concrete > generic
             let is_wrapped = from_ty.contains(to_ty);
-            if !is_wrapped {
-                if ty_bnd.len() == 0 {
-                    // to_ty could be arbitrary type
-                    if from_ty.is_numeric() || from_ty.is_str() || from_ty.is_char() {
-                        Comparison::Less
-                    } else {
-                        Comparison::Noidea
-                    }
-                } else {
-                    let mut res = Comparison::Noidea;
-                    for satisfied_ty in ty_bnd {
-                        if (from_ty.is_numeric() || from_ty.is_str() || from_ty.is_char())
-                            && (satisfied_ty.is_bool() || satisfied_ty.is_str() || satisfied_ty.is_char() || satisfied_ty.is_enum()) {
-                            res = Comparison::Less;
+            if lc.to_guarantee().contains(Guarantee::ANY_BIT_PATTERN) {
+                // to_ty accepts every bit pattern of from_ty
+                Comparison::Greater
+            } else if !is_wrapped {
+                match restrictions_of(tcx, p_env, from_ty) {
+                    None => Comparison::Unknown,
+                    Some(from_restrictions) => {
+                        if ty_bnd.len() == 0 {
+                            // to_ty could be arbitrary type, e.g. one rejecting the raw bytes of from_ty
+                            if from_restrictions.is_empty() {
+                                Comparison::Less
+                            } else {
+                                Comparison::Noidea
+                            }
+                        } else {
+                            let mut res = Comparison::Noidea;
+                            for satisfied_ty in ty_bnd {
+                                if let Some(to_restrictions) = restrictions_of(tcx, p_env, satisfied_ty) {
+                                    let violated = validity::violated(&from_restrictions, &to_restrictions);
+                                    if !violated.is_empty() {
+                                        res = Comparison::Less;
+                                        restrictions.extend(violated);
+                                    }
+                                }
+                            }
+                            res
                         }
-                    }
-                    res
+                    },
                 }
             } else {
                 Comparison::Equal
@@ -697,23 +814,37 @@ impl<'tcx> ValueChecker<'tcx> {
             from_ty: from_ty, 
             to_ty: to_ty,
             value_status: val_status,
+            restrictions,
         }
     }
 
     pub fn get_val_status(&self) -> Comparison {
         self.value_status
     }
+
+    /// Fields of the target type that reject some bit patterns of the source
+    pub fn restrictions(&self) -> &[Restriction<'tcx>] {
+        &self.restrictions
+    }
+
+    /// e.g. `["`Header.kind: Kind` only accepts 0..=2"]`
+    pub fn restriction_notes(&self) -> Vec<String> {
+        self.restrictions.iter().map(Restriction::describe).collect()
+    }
 }
 
-pub struct GenericChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+/// How deep `GenericChecker` follows generic impls when checking their bounds, e.g. one level
+/// for `[T; N] where T: Pod`
+const GENERIC_IMPL_DEPTH: usize = 4;
+
+pub struct GenericChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
     trait_set: HashSet<Ty<'tcx>>,
 }
 
-impl<'tcx> GenericChecker<'tcx> {
-    pub fn new(rc: RuMorphCtxt<'tcx>, p_env: ParamEnv<'tcx>, from_ty: Ty<'tcx>, to_ty: Ty<'tcx>) -> Self {
+impl<'a, 'tcx> GenericChecker<'a, 'tcx> {
+    pub fn new(rc: RuMorphCtxt<'a, 'tcx>, p_env: ParamEnv<'tcx>, from_ty: Ty<'tcx>, to_ty: Ty<'tcx>) -> Self {
         let tcx = rc.tcx();
-        let hir = tcx.hir();
 
         let mut trait_bnd_set: HashSet<String> = HashSet::new();
         let mut satisfied_ty_set: HashSet<Ty<'tcx>> = HashSet::new();
@@ -725,64 +856,33 @@ impl<'tcx> GenericChecker<'tcx> {
             if let Some(trait_pred) = cb.to_opt_poly_trait_pred() {
                 let trait_def_id = trait_pred.def_id();
                 let trait_name = tcx.def_path_str(trait_def_id);
-                trait_bnd_set.insert(trait_name.clone());
+                if !trait_bnd_set.insert(trait_name.clone()) {
+                    continue;
+                }
                 progress_info!("current trait name: ({})", trait_name);
+                // bounds like `Clone` say nothing about the type, and are implemented everywhere
+                if rc.marker_traits().guarantee(&trait_name) == Some(Guarantee::empty()) {
+                    continue;
+                }
 
-                // for each implementation
-                for &impl_id in hir.trait_impls(trait_def_id) {
-                    // impl_id: LocalDefId
-                    let impl_owner_id = hir.expect_owner(impl_id).def_id();
-                    let item = hir.item(ItemId { owner_id: impl_owner_id});
-                    if_chain! {
-                        if let ItemKind::Impl(impl_item) = item.kind;
-                        if impl_item.polarity == ImplPolarity::Positive;
-                        if let Some(binder) = tcx.impl_trait_ref(impl_id);
-                        then {
-                            let trait_ref = binder.skip_binder();
-                            let impl_ty = trait_ref.self_ty();
-                            match impl_ty.kind() {
-                                TyKind::Adt(adt_def, impl_trait_substs) => {
-                                    let adt_did = adt_def.did();
-                                    let adt_ty = tcx.type_of(adt_did).skip_binder();
-                                    // progress_info!("{} is implemented on adt({:?})", trait_name, adt_ty);
-                                    satisfied_ty_set.insert(adt_ty);
-                                    // for gen_arg in impl_trait_substs.iter() {
-                                        //if let Some(arg_ty) = gen_arg.as_type() {
-                                            // if arg_ty.to_string() == from_ty.to_string() {
-                                            //     progress_info!("{} is implemented on from_ty: {:?}", trait_name, from_ty);
-                                            //     from_satisfied_ty_set.extend(&satisfied_ty_set);
-                                            // }
-                                            // if arg_ty.to_string() == to_ty.to_string() {
-                                            //     progress_info!("{} is implemented on to_ty: {:?}", trait_name, to_ty);
-                                            //     to_satisfied_ty_set.extend(&satisfied_ty_set);
-                                            // }
-                                            // progress_info!("{} is implemented on adt({:?})", trait_name, arg_ty);
-                                       // }
-                                    // }
-                                },
-                                TyKind::Param(p_ty) => {
-                                    let param_ty = p_ty.to_ty(tcx);
-                                    // if param_ty.to_string() == from_ty.to_string() {
-                                    //     progress_info!("{} is implemented on from_ty: {:?}", trait_name, from_ty);
-                                    //     from_satisfied_ty_set.extend(&satisfied_ty_set);
-                                    // }
-                                    // if param_ty.to_string() == to_ty.to_string() {
-                                    //     progress_info!("{} is implemented on to_ty: {:?}", trait_name, to_ty);
-                                    //     to_satisfied_ty_set.extend(&satisfied_ty_set);
-                                    // }
-                                    // progress_info!("{} is implemented on gen_param({:?})", trait_name, param_ty);
-                                },
-                                _ => {
-                                    // progress_info!("{} is implemented on {:?}", trait_name, impl_ty);
-                                    satisfied_ty_set.insert(impl_ty);
-                                },
-                            }
-                        }
+                let impls = rc.trait_impls(trait_def_id);
+                satisfied_ty_set.extend(&impls.concrete);
+                // the concrete side may be an instance of a generic impl, e.g. `[u8; 4]` of `[T; N] where T: Pod`
+                for ty in [from_ty, to_ty] {
+                    if !ty.has_param()
+                        && impls
+                            .generic
+                            .iter()
+                            .any(|generic_impl| Self::is_instance(rc, generic_impl, ty, GENERIC_IMPL_DEPTH))
+                    {
+                        satisfied_ty_set.insert(ty);
                     }
                 }
 
-                // handle known external trait e.g., Pod
-                if trait_name == "bytemuck::Pod" || trait_name == "plain::Plain" {
+                // handle marker traits whose implementors accept any bit pattern, e.g., Pod
+                if let Some(guarantee) = rc.marker_traits().guarantee(&trait_name)
+                    && guarantee.contains(Guarantee::ANY_BIT_PATTERN)
+                {
                     let ty_bnd = Self::get_satisfied_ty_for_Pod(tcx);
                     satisfied_ty_set.extend(&ty_bnd);
                     // progress_info!("current trait bound type set: {:?}", satisfied_ty_set);
@@ -791,15 +891,14 @@ impl<'tcx> GenericChecker<'tcx> {
         }
 
         // check trait_bnd_set
-        let std_trait_set = HashSet::from([
-            String::from("std::marker::Copy"), 
-            String::from("std::clone::Clone"), 
-            String::from("std::marker::Sized")
-        ]);
-        // if all trait_bound is std::marker, then we could assume it to be arbitrary type
+        // if all trait_bound are marker traits without guarantees (e.g. Copy),
+        // then we could assume it to be arbitrary type
         // to avoid messing up with build type manually
         // we just clear the satisfied ty set
-        if trait_bnd_set.is_subset(&std_trait_set) {
+        let no_guarantee = trait_bnd_set
+            .iter()
+            .all(|trait_name| rc.marker_traits().guarantee(trait_name) == Some(Guarantee::empty()));
+        if no_guarantee {
             satisfied_ty_set.clear();
         }
 
@@ -815,6 +914,49 @@ impl<'tcx> GenericChecker<'tcx> {
         self.trait_set.clone()
     }
 
+    // whether `ty` implements the trait through a concrete impl, the knowledge base or a generic impl
+    fn implements(
+        rc: RuMorphCtxt<'a, 'tcx>,
+        trait_def_id: DefId,
+        ty: Ty<'tcx>,
+        depth: usize,
+    ) -> bool {
+        let tcx = rc.tcx();
+        match rc.marker_traits().guarantee(&tcx.def_path_str(trait_def_id)) {
+            // e.g. `Copy`, which isn't implemented in source for the primitives
+            Some(guarantee) if guarantee.is_empty() => return true,
+            Some(guarantee)
+                if guarantee.contains(Guarantee::ANY_BIT_PATTERN) && Self::get_satisfied_ty_for_Pod(tcx).contains(&ty) =>
+            {
+                return true
+            },
+            _ => {},
+        }
+        let impls = rc.trait_impls(trait_def_id);
+        impls.concrete.contains(&ty)
+            || (depth > 0
+                && impls
+                    .generic
+                    .iter()
+                    .any(|generic_impl| Self::is_instance(rc, generic_impl, ty, depth - 1)))
+    }
+
+    // whether `generic_impl` applies to `ty`, i.e. `ty` matches its self type and the bound parameters
+    // satisfy its where clauses
+    fn is_instance(
+        rc: RuMorphCtxt<'a, 'tcx>,
+        generic_impl: &GenericImpl<'tcx>,
+        ty: Ty<'tcx>,
+        depth: usize,
+    ) -> bool {
+        let Some(bindings) = generic_impl.instantiate(ty) else { return false };
+        generic_impl.bounds.iter().all(|bound| match bindings.get(&bound.skip_binder().self_ty()) {
+            Some(&bound_ty) => Self::implements(rc, bound.def_id(), bound_ty, depth),
+            // bounds on projections aren't checked
+            None => true,
+        })
+    }
+
     fn get_satisfied_ty_for_Pod(tcx: TyCtxt<'tcx>) -> HashSet<Ty<'tcx>> {
         let mut satisfied_ty_set_for_pod: HashSet<Ty<'tcx>> = HashSet::new();
         // f64, u64, i8, i32, u8, i16, u16, u32, usize, i128, isize, i64, u128, f32
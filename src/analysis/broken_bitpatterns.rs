@@ -1,4 +1,4 @@
-use rustc_hir::{def_id::DefId, BodyId, Unsafety};
+use rustc_hir::{def_id::DefId, BodyId};
 use rustc_middle::mir::{Operand, StatementKind, Rvalue, CastKind, Place, HasLocalDecls, AggregateKind};
 use rustc_middle::mir::RETURN_PLACE;
 use rustc_middle::ty::{self, Ty, Instance, ParamEnv, TyKind};
@@ -10,8 +10,9 @@ use termcolor::Color;
 use crate::graph::GraphTaint;
 use crate::prelude::*;
 use crate::{
-    analysis::{AnalysisKind, IntoReportLevel, ValueChecker, Comparison},
-    graph::TaintAnalyzer,
+    analysis::{AnalysisKind, IntoReportLevel, ValueChecker, Comparison, join_notes},
+    dataflow::body_taint_analyzer,
+    graph::TaintEngine,
     ir,
     paths::{self, *},
     report::{Report, ReportLevel},
@@ -41,12 +42,12 @@ impl AnalysisError for BrokenBitPatternsError {
 
 
 
-pub struct BrokenBitPatternsChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct BrokenBitPatternsChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
 }
 
-impl<'tcx> BrokenBitPatternsChecker<'tcx> {
-    pub fn new(rcx: RuMorphCtxt<'tcx>) -> Self {
+impl<'a, 'tcx> BrokenBitPatternsChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
         BrokenBitPatternsChecker { rcx }
     }
 
@@ -96,13 +97,14 @@ impl<'tcx> BrokenBitPatternsChecker<'tcx> {
                         color_span.add_sub_span(Color::Green, span);
                     }
 
-                    rumorph_report(Report::with_color_span(
+                    self.rcx.report(Report::with_color_span(
                         tcx,
                         behavior_flag.report_level(visible),
                         AnalysisKind::BrokenBitPatterns(behavior_flag),
                         format!(
-                            "Potential broken bit patterns issue in `{}`",
-                            tcx.def_path_str(hir_map.body_owner_def_id(body_id).to_def_id())
+                            "Potential broken bit patterns issue in `{}`{}",
+                            tcx.def_path_str(hir_map.body_owner_def_id(body_id).to_def_id()),
+                            join_notes(status.restriction_notes())
                         ),
                         &color_span,
                     ))
@@ -125,6 +127,8 @@ mod inner {
         unresolvable_generic_functions: Vec<Span>,
         ty_convs: Vec<Span>,
         creation: Vec<Span>,
+        /// restricted fields of the conversion targets, see `ValueChecker::restriction_notes`
+        restriction_notes: Vec<String>,
         behavior_flag: BehaviorFlag,
     }
 
@@ -152,17 +156,30 @@ mod inner {
         pub fn ty_conv_spans(&self) -> &Vec<Span> {
             &self.ty_convs
         }
+
+        pub fn restriction_notes(&self) -> &Vec<String> {
+            &self.restriction_notes
+        }
+
+        // a note is reported once, however many conversions it applies to
+        fn add_restriction_notes(&mut self, notes: Vec<String>) {
+            for note in notes {
+                if !self.restriction_notes.contains(&note) {
+                    self.restriction_notes.push(note);
+                }
+            }
+        }
     }
 
     pub struct BrokenBitPatternsBodyAnalyzer<'a, 'tcx> {
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'a, 'tcx>,
         body: &'a ir::Body<'tcx>,
         param_env: ParamEnv<'tcx>,
         status: BrokenBitPatternsStatus,
     }
 
     impl<'a, 'tcx> BrokenBitPatternsBodyAnalyzer<'a, 'tcx> {
-        fn new(rcx: RuMorphCtxt<'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        fn new(rcx: RuMorphCtxt<'a, 'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
             BrokenBitPatternsBodyAnalyzer {
                 rcx,
                 body,
@@ -171,7 +188,7 @@ mod inner {
             }
         }
 
-        pub fn analyze_body(rcx: RuMorphCtxt<'tcx>, body_id: BodyId) -> Option<BrokenBitPatternsStatus> {
+        pub fn analyze_body(rcx: RuMorphCtxt<'_, 'tcx>, body_id: BodyId) -> Option<BrokenBitPatternsStatus> {
             let hir_map = rcx.tcx().hir();
             let body_did = hir_map.body_owner_def_id(body_id).to_def_id();
 
@@ -184,8 +201,7 @@ mod inner {
                 trace_calls_in_body(rcx, body_did);
                 None
             } else if ContainsUnsafe::contains_unsafe(rcx.tcx(), body_id) {
-                let fn_sig = rcx.tcx().fn_sig(body_did).skip_binder();
-                if let Unsafety::Unsafe = fn_sig.unsafety() {
+                if utils::is_unsafe_fn(rcx.tcx(), body_did) {
                     progress_info!("The function is unsafe");
                     None
                 } else {
@@ -213,17 +229,18 @@ mod inner {
 
         fn analyze(mut self, mut tconv_source: Vec<usize>) -> BrokenBitPatternsStatus {
             let loc_decls = self.body.local_decls();
-            let mut taint_analyzer = TaintAnalyzer::new(self.body);
+            let mut taint_analyzer = body_taint_analyzer(self.rcx, self.body);
 
-            for statement in self.body.statements() {
+            for (_location, statement) in self.body.statements() {
                 // statement here is mir::Statement without translation
                 // while iterating statements, we plan to mark ty conv as source / plain deref as sink
                 // progress_info!("statement: {:?}", statement);
-                match statement.kind {
+                match &statement.kind {
                     StatementKind::Assign(box (lplace, rval)) => {
                         // rhs
                         match rval {
                             Rvalue::Cast(cast_kind, op, to_ty) => {
+                                let to_ty = *to_ty;
                                 match cast_kind {
                                     CastKind::PtrToPtr => {
                                         progress_info!("cast::ptr-ptr");
@@ -236,7 +253,7 @@ mod inner {
                                                 let pl = get_place_from_op(&op);
                                                 match pl {
                                                     Ok(place) => {
-                                                        let id = place.local.index();
+                                                        let id = self.body.place_node(&place);
 
                                                         // if A could be generic type or composite type, and B is primitive type, taint as source
                                                         match value_status {
@@ -259,6 +276,7 @@ mod inner {
                                                                     self.status
                                                                         .creation
                                                                         .push(statement.source_info.span);
+                                                                    self.status.add_restriction_notes(vc.restriction_notes());
                                                                     // progress_info!("cast leads to ub in this statement");
                                                                 }
                                                             },
@@ -291,7 +309,7 @@ mod inner {
                                                 let pl = get_place_from_op(&op);
                                                 match pl {
                                                     Ok(place) => {
-                                                        let id = place.local.index();
+                                                        let id = self.body.place_node(&place);
 
                                                         match value_status {
                                                             // make sure it is not kind of bug 1
@@ -314,6 +332,7 @@ mod inner {
                                                                     self.status
                                                                         .creation
                                                                         .push(statement.source_info.span);
+                                                                    self.status.add_restriction_notes(vc.restriction_notes());
                                                                     // progress_info!("cast leads to ub in this statement");
                                                                 }
                                                             },
@@ -360,11 +379,11 @@ mod inner {
                         progress_info!("terminator with symbol: {:?}", symbol_vec);
                         let sym = symbol_vec[ symbol_vec.len() - 1 ].as_str();
                         if sym.contains("alloc") {
-                            let id = dest.local.index();
-                            taint_analyzer
-                                .clear_source(id);
+                            // a fresh allocation is not derived from the converted pointer
+                            let id = self.body.place_node(&dest);
+                            taint_analyzer.mark_sanitizer(id);
                         } else if paths::STR_UNCHECKED_LIST.contains(&symbol_vec) {
-                            let id = dest.local.index();
+                            let id = self.body.place_node(&dest);
                             // for conv_id in tconv_source.iter() {
                             // if taint_analyzer.is_reachable(*conv_id, id) {
                             taint_analyzer.mark_at_once(id, &BehaviorFlag::TRANSMUTE);
@@ -417,11 +436,12 @@ mod inner {
             }
 
             self.status.behavior_flag = taint_analyzer.propagate();
+            self.rcx.dump_graphs("BrokenBitPatterns", self.body, &*taint_analyzer);
             self.status
         }
     }
 
-    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'tcx>, body_def_id: DefId) {
+    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, body_def_id: DefId) {
         warn!("Paths discovery function has been detected");
         if let Ok(body) = rcx.translate_body(body_def_id).as_ref() {
             for terminator in body.terminators() {
@@ -471,7 +491,7 @@ fn get_place_from_op<'tcx>(op: &Operand<'tcx>) -> Result<Place<'tcx>, &'static s
     }
 }
 
-fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
+fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'_, 'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
     match op {
         Operand::Copy(place) | Operand::Move(place) => {
             Ok(place.ty(bd, rcx.tcx()).ty)
@@ -529,4 +549,8 @@ impl GraphTaint for BehaviorFlag {
     fn join(&mut self, taint: &Self) {
         *self |= *taint;
     }
+
+    fn remove(&mut self, taint: &Self) {
+        *self &= !*taint;
+    }
 }
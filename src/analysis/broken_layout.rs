@@ -1,4 +1,4 @@
-use rustc_hir::{def_id::DefId, BodyId, Unsafety};
+use rustc_hir::{def_id::DefId, BodyId};
 use rustc_middle::mir::{Operand, StatementKind, Rvalue, CastKind, Place, HasLocalDecls, AggregateKind};
 use rustc_middle::mir::RETURN_PLACE;
 use rustc_middle::ty::{self, Ty, Instance, ParamEnv, TyKind};
@@ -10,8 +10,9 @@ use termcolor::Color;
 use crate::graph::GraphTaint;
 use crate::prelude::*;
 use crate::{
-    analysis::{AnalysisKind, IntoReportLevel, LayoutChecker, Comparison},
-    graph::TaintAnalyzer,
+    analysis::{AnalysisKind, IntoReportLevel, LayoutChecker, Comparison, join_notes},
+    dataflow::body_taint_analyzer,
+    graph::TaintEngine,
     ir,
     paths::{self, *},
     report::{Report, ReportLevel},
@@ -39,12 +40,12 @@ impl AnalysisError for BrokenLayoutError {
     }
 }
 
-pub struct BrokenLayoutChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct BrokenLayoutChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
 }
 
-impl<'tcx> BrokenLayoutChecker<'tcx> {
-    pub fn new(rcx: RuMorphCtxt<'tcx>) -> Self {
+impl<'a, 'tcx> BrokenLayoutChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
         BrokenLayoutChecker { rcx }
     }
 
@@ -92,13 +93,18 @@ impl<'tcx> BrokenLayoutChecker<'tcx> {
                         color_span.add_sub_span(Color::Green, span);
                     }
 
-                    rumorph_report(Report::with_color_span(
+                    for &span in status.unknown_layout_spans() {
+                        color_span.add_sub_span(Color::Magenta, span);
+                    }
+
+                    self.rcx.report(Report::with_color_span(
                         tcx,
                         behavior_flag.report_level(true),
                         AnalysisKind::BrokenLayout(behavior_flag),
                         format!(
-                            "Potential broken layout issue in `{}`",
-                            tcx.def_path_str(hir_map.body_owner_def_id(body_id).to_def_id())
+                            "Potential broken layout issue in `{}`{}",
+                            tcx.def_path_str(hir_map.body_owner_def_id(body_id).to_def_id()),
+                            join_notes(status.target_notes())
                         ),
                         &color_span,
                     ))
@@ -120,6 +126,10 @@ mod inner {
         plain_deref: Vec<Span>,
         unresolvable_generic_functions: Vec<Span>,
         ty_convs: Vec<Span>,
+        /// conversions whose layout can't be computed
+        unknown_layouts: Vec<Span>,
+        /// layout targets on which a conversion breaks and unknown layouts, see `LayoutChecker::target_notes`
+        target_notes: Vec<String>,
         behavior_flag: BehaviorFlag,
     }
 
@@ -147,17 +157,34 @@ mod inner {
         pub fn ty_conv_spans(&self) -> &Vec<Span> {
             &self.ty_convs
         }
+
+        pub fn unknown_layout_spans(&self) -> &Vec<Span> {
+            &self.unknown_layouts
+        }
+
+        pub fn target_notes(&self) -> &Vec<String> {
+            &self.target_notes
+        }
+
+        // a note is reported once, however many conversions it applies to
+        fn add_target_notes(&mut self, notes: Vec<String>) {
+            for note in notes {
+                if !self.target_notes.contains(&note) {
+                    self.target_notes.push(note);
+                }
+            }
+        }
     }
 
     pub struct BrokenLayoutBodyAnalyzer<'a, 'tcx> {
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'a, 'tcx>,
         body: &'a ir::Body<'tcx>,
         param_env: ParamEnv<'tcx>,
         status: BrokenLayoutStatus,
     }
 
     impl<'a, 'tcx> BrokenLayoutBodyAnalyzer<'a, 'tcx> {
-        fn new(rcx: RuMorphCtxt<'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        fn new(rcx: RuMorphCtxt<'a, 'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
             BrokenLayoutBodyAnalyzer {
                 rcx,
                 body,
@@ -166,7 +193,7 @@ mod inner {
             }
         }
 
-        pub fn analyze_body(rcx: RuMorphCtxt<'tcx>, body_id: BodyId) -> Option<BrokenLayoutStatus> {
+        pub fn analyze_body(rcx: RuMorphCtxt<'_, 'tcx>, body_id: BodyId) -> Option<BrokenLayoutStatus> {
             let hir_map = rcx.tcx().hir();
             let body_did = hir_map.body_owner_def_id(body_id).to_def_id();
 
@@ -179,8 +206,7 @@ mod inner {
                 trace_calls_in_body(rcx, body_did);
                 None
             } else if ContainsUnsafe::contains_unsafe(rcx.tcx(), body_id) {
-                let fn_sig = rcx.tcx().fn_sig(body_did).skip_binder();
-                if let Unsafety::Unsafe = fn_sig.unsafety() {
+                if utils::is_unsafe_fn(rcx.tcx(), body_did) {
                     progress_info!("The function is unsafe");
                     None
                 } else {
@@ -206,19 +232,19 @@ mod inner {
         }
 
         fn analyze(mut self) -> BrokenLayoutStatus {
-            let mut taint_analyzer = TaintAnalyzer::new(self.body);
+            let mut taint_analyzer = body_taint_analyzer(self.rcx, self.body);
 
-            for statement in self.body.statements() {
+            for (_location, statement) in self.body.statements() {
                 // statement here is mir::Statement without translation
                 // while iterating statements, we plan to mark ty conv as source / plain deref as sink
                 progress_info!("{:?}", statement);
-                match statement.kind {
+                match &statement.kind {
                     StatementKind::Assign(box (lplace, rval)) => {
                         // lhs could also contains deref operation
                         if lplace.is_indirect() {
                             // contains deref projection
                             // progress_info!("warn::deref on place:{}", lplace.local.index());
-                            taint_analyzer.mark_sink(lplace.local.index());
+                            taint_analyzer.mark_sinks(self.body.read_nodes(&lplace));
                             self.status
                                 .plain_deref
                                 .push(statement.source_info.span);
@@ -226,6 +252,7 @@ mod inner {
                         // rhs
                         match rval {
                             Rvalue::Cast(cast_kind, op, to_ty) => {
+                                let to_ty = *to_ty;
                                 match cast_kind {
                                     CastKind::PtrToPtr => {
                                         progress_info!("cast::ptr-ptr");
@@ -243,17 +270,25 @@ mod inner {
                                                 let pl = get_place_from_op(&op);
                                                 match pl {
                                                     Ok(place) => {
-                                                        let id = place.local.index();
+                                                        let id = self.body.place_node(&place);
 
                                                         // if A's align < B's align, taint as source
                                                         match align_status {
                                                             Comparison::Less => {
-                                                                let id2 = lplace.local.index();
+                                                                let id2 = self.body.place_node(&lplace);
                                                                 // progress_info!("warn::align from id{} to lplace{}", id, id2);
                                                                 taint_analyzer.mark_source(id2, &BehaviorFlag::CAST);
                                                                 self.status
                                                                     .ty_convs
                                                                     .push(statement.source_info.span);
+                                                                self.status.add_target_notes(lc.target_notes());
+                                                            },
+                                                            // not a source, but the report says what went unchecked
+                                                            Comparison::Unknown => {
+                                                                self.status
+                                                                    .unknown_layouts
+                                                                    .push(statement.source_info.span);
+                                                                self.status.add_target_notes(lc.target_notes());
                                                             },
                                                             _ => {},
                                                         }
@@ -287,17 +322,25 @@ mod inner {
                                                 let pl = get_place_from_op(&op);
                                                 match pl {
                                                     Ok(place) => {
-                                                        let id = place.local.index();
+                                                        let id = self.body.place_node(&place);
 
                                                         // if A's align < B's align, taint as source
                                                         match align_status {
                                                             Comparison::Less => {
                                                                 // progress_info!("warn::align");
-                                                                let id2 = lplace.local.index();
+                                                                let id2 = self.body.place_node(&lplace);
                                                                 taint_analyzer.mark_source(id2, &BehaviorFlag::TRANSMUTE);
                                                                 self.status
                                                                     .ty_convs
                                                                     .push(statement.source_info.span);
+                                                                self.status.add_target_notes(lc.target_notes());
+                                                            },
+                                                            // not a source, but the report says what went unchecked
+                                                            Comparison::Unknown => {
+                                                                self.status
+                                                                    .unknown_layouts
+                                                                    .push(statement.source_info.span);
+                                                                self.status.add_target_notes(lc.target_notes());
                                                             },
                                                             _ => {},
                                                         }
@@ -320,12 +363,12 @@ mod inner {
                             | Rvalue::ShallowInitBox(op, _) => {
                                 match op {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
+                                        let ids = self.body.read_nodes(&pl);
                                         // progress_info!("[dbg] lplace: {}, rplace: {}", lplace.local.index(), pl.local.index());
                                         if pl.is_indirect() {
                                             // contains deref projection
                                             // progress_info!("warn::deref on place:{}", id);
-                                            taint_analyzer.mark_sink(id);
+                                            taint_analyzer.mark_sinks(ids);
                                             self.status
                                                 .plain_deref
                                                 .push(statement.source_info.span);
@@ -339,11 +382,11 @@ mod inner {
                             | Rvalue::Len(pl)
                             | Rvalue::Discriminant(pl)
                             | Rvalue::CopyForDeref(pl) => {
-                                let id = pl.local.index();
+                                let ids = self.body.read_nodes(&pl);
                                 if pl.is_indirect() {
                                     // contains deref projection
                                     // progress_info!("warn::deref on place:{}", id);
-                                    taint_analyzer.mark_sink(id);
+                                    taint_analyzer.mark_sinks(ids);
                                     self.status
                                         .plain_deref
                                         .push(statement.source_info.span);
@@ -372,15 +415,26 @@ mod inner {
                         // Check for lifetime bypass
                         let symbol_vec = ext.get_def_path(callee_did);
                         let sym = symbol_vec[ symbol_vec.len() - 1 ].as_str();
+                        // a local callee dereferencing an argument counts as a deref at the call site
+                        if let Some(summary) = terminator.static_callee().and_then(|callee| self.rcx.summary(callee)) {
+                            for &arg in &summary.deref_args {
+                                if let Some(pl) = args.get(arg).and_then(|arg| arg.place()) {
+                                    taint_analyzer.mark_sinks(self.body.read_nodes(&pl));
+                                    self.status
+                                        .plain_deref
+                                        .push(terminator.original.source_info.span);
+                                }
+                            }
+                        }
                         if sym.contains("alloc") {
-                            let id = dest.local.index();
-                            taint_analyzer
-                                .clear_source(id);
+                            // a fresh allocation is not derived from the converted pointer
+                            let id = self.body.place_node(&dest);
+                            taint_analyzer.mark_sanitizer(id);
                         } else if sym.contains("unaligned") {
                             for arg in args {
                                 match arg {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
+                                        let id = self.body.place_node(&pl);
                                         taint_analyzer
                                             .clear_source(id);
                                     },
@@ -393,8 +447,8 @@ mod inner {
                                 // arg: mir::Operand
                                 match arg {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
-                                        taint_analyzer.mark_sink(id);
+                                        let ids = self.body.read_nodes(&pl);
+                                        taint_analyzer.mark_sinks(ids);
                                         self.status
                                             .strong_bypasses
                                             .push(terminator.original.source_info.span);
@@ -407,8 +461,8 @@ mod inner {
                             for arg in args {
                                 match arg {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
-                                        taint_analyzer.mark_sink(id);
+                                        let ids = self.body.read_nodes(&pl);
+                                        taint_analyzer.mark_sinks(ids);
                                         self.status
                                             .weak_bypasses
                                             .push(terminator.original.source_info.span);
@@ -438,11 +492,15 @@ mod inner {
             }
 
             self.status.behavior_flag = taint_analyzer.propagate();
+            if !self.status.unknown_layouts.is_empty() {
+                self.status.behavior_flag |= BehaviorFlag::UNKNOWN_LAYOUT;
+            }
+            self.rcx.dump_graphs("BrokenLayout", self.body, &*taint_analyzer);
             self.status
         }
     }
 
-    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'tcx>, body_def_id: DefId) {
+    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, body_def_id: DefId) {
         warn!("Paths discovery function has been detected");
         if let Ok(body) = rcx.translate_body(body_def_id).as_ref() {
             for terminator in body.terminators() {
@@ -492,7 +550,7 @@ fn get_place_from_op<'tcx>(op: &Operand<'tcx>) -> Result<Place<'tcx>, &'static s
     }
 }
 
-fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
+fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'_, 'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
     match op {
         Operand::Copy(place) | Operand::Move(place) => {
             Ok(place.ty(bd, rcx.tcx()).ty)
@@ -511,6 +569,8 @@ bitflags! {
     pub struct BehaviorFlag: u16 {
         const CAST = 0b00000001;
         const TRANSMUTE = 0b00000010;
+        /// not a taint, set when a conversion's layout can't be computed
+        const UNKNOWN_LAYOUT = 0b00000100;
     }
 }
 
@@ -519,6 +579,10 @@ impl IntoReportLevel for BehaviorFlag {
         use BehaviorFlag as Flag;
 
         let high = Flag::CAST | Flag::TRANSMUTE;
+        // only unchecked conversions, nothing was found to break
+        if *self == Flag::UNKNOWN_LAYOUT {
+            return ReportLevel::Info;
+        }
         //let med = Flag::READ_FLOW | Flag::COPY_FLOW | Flag::WRITE_FLOW;
 
         // if !(*self & high).is_empty() {
@@ -545,4 +609,8 @@ impl GraphTaint for BehaviorFlag {
     fn join(&mut self, taint: &Self) {
         *self |= *taint;
     }
+
+    fn remove(&mut self, taint: &Self) {
+        *self &= !*taint;
+    }
 }
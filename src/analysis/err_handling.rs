@@ -12,7 +12,8 @@ use crate::graph::GraphTaint;
 use crate::prelude::*;
 use crate::{
     analysis::{AnalysisKind, IntoReportLevel, LayoutChecker, Comparison},
-    graph::TaintAnalyzer,
+    dataflow::body_taint_analyzer,
+    graph::TaintEngine,
     ir,
     paths::{self, *},
     report::{Report, ReportLevel},
@@ -40,12 +41,12 @@ impl AnalysisError for ErrHandleError {
     }
 }
 
-pub struct ErrHandleChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct ErrHandleChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
 }
 
-impl<'tcx> ErrHandleChecker<'tcx> {
-    pub fn new(rcx: RuMorphCtxt<'tcx>) -> Self {
+impl<'a, 'tcx> ErrHandleChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
         ErrHandleChecker { rcx }
     }
 
@@ -87,7 +88,7 @@ impl<'tcx> ErrHandleChecker<'tcx> {
                         color_span.add_sub_span(Color::Red, span);
                     }
 
-                    rumorph_report(Report::with_color_span(
+                    self.rcx.report(Report::with_color_span(
                         tcx,
                         behavior_flag.report_level(true),
                         AnalysisKind::ErrHandle(behavior_flag),
@@ -162,14 +163,14 @@ mod inner {
     }
 
     pub struct ErrHandleBodyAnalyzer<'a, 'tcx> {
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'a, 'tcx>,
         body: &'a ir::Body<'tcx>,
         param_env: ParamEnv<'tcx>,
         status: ErrHandleStatus,
     }
 
     impl<'a, 'tcx> ErrHandleBodyAnalyzer<'a, 'tcx> {
-        fn new(rcx: RuMorphCtxt<'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        fn new(rcx: RuMorphCtxt<'a, 'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
             ErrHandleBodyAnalyzer {
                 rcx,
                 body,
@@ -178,7 +179,7 @@ mod inner {
             }
         }
 
-        pub fn analyze_body(rcx: RuMorphCtxt<'tcx>, body_id: BodyId) -> Option<ErrHandleStatus> {
+        pub fn analyze_body(rcx: RuMorphCtxt<'_, 'tcx>, body_id: BodyId) -> Option<ErrHandleStatus> {
             let hir_map = rcx.tcx().hir();
             let body_did = hir_map.body_owner_def_id(body_id).to_def_id();
 
@@ -207,7 +208,7 @@ mod inner {
         }
 
         fn analyze(mut self) -> ErrHandleStatus {
-            let mut taint_analyzer = TaintAnalyzer::new(self.body);
+            let mut taint_analyzer = body_taint_analyzer(self.rcx, self.body);
 
             let mut error_kind_map = HashMap::new();
             let mut sink_loc_map = HashMap::new();
@@ -219,20 +220,7 @@ mod inner {
 
             let mut checked_source = Vec::new();
 
-            let mut cleanup_bb = Vec::new();
             let mut idx = 0;
-            for bb in &self.body.basic_blocks {
-                match &bb.terminator.kind {
-                    ir::TerminatorKind::Return => {
-                        cleanup_bb.push(idx);
-                    },
-                    _ => {},
-                }
-                idx = idx + 1;
-            }
-            progress_info!("cleanup_bb: {:?}", cleanup_bb);
-            
-            idx = 0;
             for bb in &self.body.basic_blocks {
                 progress_info!("basic block index: {:?}", idx);
                 for s in &bb.statements {
@@ -242,12 +230,9 @@ mod inner {
                 idx = idx + 1;
             }
 
-            // lookup_char_pos
-
-            for (bb_idx, terminator) in self.body.terminators().enumerate() {
-                let sp = terminator.original.source_info.span;
-                let sm = self.rcx.tcx().sess.source_map();
-                let loc = sm.lookup_char_pos(sp.lo()).line;
+            for (bb, terminator) in self.body.located_terminators() {
+                let bb_idx = bb.index();
+                let loc = self.body.terminator_line(bb);
                 match &terminator.kind {
                     ir::TerminatorKind::StaticCall {
                         callee_did,
@@ -265,7 +250,7 @@ mod inner {
                         // checked_* return Option
                         // expect, ok_or, ok_or_else, map, map_or, map_or_else, unwrap, unwrap_or..
                         
-                        let id = dest.local.index();
+                        let id = self.body.place_node(&dest);
                         if sym.contains("checked_") {
                             taint_analyzer.mark_source(id, &BehaviorFlag::CHECKEDCALL);
                             checked_idx = bb_idx;
@@ -294,46 +279,30 @@ mod inner {
                             }
                         }
                     },
-                    ir::TerminatorKind::SwitchInt {
-                        discr,
-                        targets
-                    } => {
+                    ir::TerminatorKind::SwitchInt { discr, targets } => {
                         // check whether SwitchInt is the direct successor of checked_* functions
                         if (checked_idx != usize::MAX) && (self.body.is_direct_successor(checked_idx, bb_idx)) {
-                            // check whether the targets contain return statement
-                            let mut not_return: bool = false;
-                            let mut return_arrs = Vec::new();
-                            for tg in targets.all_targets() {
-                                // breadth first search
-                                for cb in &cleanup_bb {
-                                    progress_info!("{:?} -> {:?}", tg.index(), *cb);
-                                    return_arrs.push(self.body.arr_return(tg.index(), *cb));
-                                }
-                            }
-
-                            progress_info!("return arrays: {:?}", return_arrs);
-
-                            for (i, sarr1) in return_arrs.iter().enumerate() {
-                                for (j, sarr2) in return_arrs.iter().enumerate() {
-                                    if let Some(path1) = sarr1 {
-                                        if let Some(path2) = sarr2 {
-                                            if i != j && is_subarray(&path1[1..], &path2[1..]) {
-                                                not_return |= true; 
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            // the error is ignored if no branch panics and the branches join
+                            // again before the epilogue, i.e. none of them returns early
+                            let not_return = targets
+                                .all_targets()
+                                .iter()
+                                .all(|target| self.body.always_returns(target.index()))
+                                && self
+                                    .body
+                                    .immediate_post_dominator(bb_idx)
+                                    .map_or(false, |join| !self.body.is_epilogue(join));
 
                             progress_info!("no return? :{:?}", not_return);
                             
                             if not_return {
                                 match discr {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
-                                        taint_analyzer.mark_sink(id);
-                                        error_kind_map.insert(id, "ignore");
-                                        sink_loc_map.insert(id, loc);
+                                        for id in self.body.read_nodes(&pl) {
+                                            taint_analyzer.mark_sink(id);
+                                            error_kind_map.insert(id, "ignore");
+                                            sink_loc_map.insert(id, loc);
+                                        }
                                         self.status
                                             .branch_handles
                                             .push(terminator.original.source_info.span);
@@ -349,15 +318,16 @@ mod inner {
 
             // if already visiting all terminators
             // there is unwrap/ok/map, but not find panic/expect to handle it
-            // then error is handled correctly and should not alarm
+            // then error is handled correctly and the handled value is no longer tainted
             if !immediate_status.is_empty() {
-                progress_info!("clear source!! not alarm!!");
+                progress_info!("sanitize handled values!! not alarm!!");
                 for src_id in immediate_status {
-                    taint_analyzer.clear_source(src_id);
+                    taint_analyzer.mark_sanitizer_mask(src_id, &BehaviorFlag::CHECKEDCALL);
                 }
             }
 
             self.status.behavior_flag = taint_analyzer.propagate();
+            self.rcx.dump_graphs("ErrHandle", self.body, &*taint_analyzer);
             
             // there are two kinds of error stored in error_kind_map: ignore and panic
             for sink in taint_analyzer.get_reachable_sinks() {
@@ -382,7 +352,7 @@ mod inner {
         }
     }
 
-    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'tcx>, body_def_id: DefId) {
+    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, body_def_id: DefId) {
         warn!("Paths discovery function has been detected");
         if let Ok(body) = rcx.translate_body(body_def_id).as_ref() {
             for terminator in body.terminators() {
@@ -403,23 +373,6 @@ mod inner {
     }
 }
 
-fn is_subarray(sub: &[usize], arr: &[usize]) -> bool {
-    if sub.is_empty() {
-        return false;
-    }
-
-    if sub.len() > arr.len() {
-        return false;
-    }
-
-    for i in 0..=(arr.len() - sub.len()) {
-        if (&arr[i..i + sub.len()] == sub) && (sub.len() > 3) {
-            return true;
-        }
-    }
-    false
-}
-
 // check whether both from_ty and to_ty are pointer types
 fn is_ptr_ty<'tcx>(from_ty: Ty<'tcx>, to_ty: Ty<'tcx>) -> bool {
     // (from_ty|to_ty) needs to be raw pointer or reference
@@ -449,7 +402,7 @@ fn get_place_from_op<'tcx>(op: &Operand<'tcx>) -> Result<Place<'tcx>, &'static s
     }
 }
 
-fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
+fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'_, 'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
     match op {
         Operand::Copy(place) | Operand::Move(place) => {
             Ok(place.ty(bd, rcx.tcx()).ty)
@@ -502,4 +455,8 @@ impl GraphTaint for BehaviorFlag {
     fn join(&mut self, taint: &Self) {
         *self |= *taint;
     }
+
+    fn remove(&mut self, taint: &Self) {
+        *self &= !*taint;
+    }
 }
@@ -0,0 +1,267 @@
+use rustc_middle::mir::mono::MonoItem;
+use rustc_middle::mir::{CastKind, Operand, Rvalue, StatementKind};
+use rustc_middle::ty::{self, Instance, InstanceDef, ParamEnv, Ty, TyCtxt, TypeVisitableExt};
+use rustc_span::Span;
+
+use std::collections::HashSet;
+use snafu::{Backtrace, Snafu};
+use termcolor::Color;
+
+use crate::prelude::*;
+use crate::{
+    analysis::{get_pointee, violated_restrictions, AnalysisKind, IntoReportLevel, LayoutChecker, Comparison},
+    ir,
+    report::{Report, ReportLevel},
+    utils,
+    validity::{self, Restriction},
+    context::RuMorphCtxt,
+    progress_info,
+};
+
+#[derive(Debug, Snafu)]
+pub enum MonoLayoutError {
+    InvalidSpan { backtrace: Backtrace },
+}
+
+impl AnalysisError for MonoLayoutError {
+    fn kind(&self) -> AnalysisErrorKind {
+        use MonoLayoutError::*;
+        match self {
+            InvalidSpan { .. } => AnalysisErrorKind::Unreachable,
+        }
+    }
+}
+
+/// Re-checks the generic casts and transmutes of local functions with the concrete types of
+/// every instantiation collected for codegen, instead of guessing with `GenericChecker`.
+/// Only instantiations reachable from the crate's codegen roots are seen, so run it on
+/// binaries or `--test` builds to cover generic library code.
+pub struct MonoLayoutChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
+}
+
+impl<'a, 'tcx> MonoLayoutChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
+        MonoLayoutChecker { rcx }
+    }
+
+    pub fn analyze(self) {
+        let tcx = self.rcx.tcx();
+
+        for instance in self.local_generic_instances() {
+            let def_id = instance.def_id();
+            progress_info!("MonoLayoutChecker::analyze({})", instance);
+
+            if let Some(status) = inner::MonoLayoutBodyAnalyzer::analyze_instance(self.rcx, instance) {
+                let behavior_flag = status.behavior_flag();
+                if behavior_flag.is_empty() {
+                    continue;
+                }
+
+                let mut color_span = unwrap_or!(
+                    utils::ColorSpan::new(tcx, tcx.def_span(def_id)).context(InvalidSpan) => continue
+                );
+                for &span in status.misaligned_spans() {
+                    color_span.add_sub_span(Color::Red, span);
+                }
+                for &span in status.invalid_value_spans() {
+                    color_span.add_sub_span(Color::Yellow, span);
+                }
+                for &span in status.unknown_layout_spans() {
+                    color_span.add_sub_span(Color::Cyan, span);
+                }
+
+                self.rcx.report(Report::with_color_span(
+                    tcx,
+                    behavior_flag.report_level(true),
+                    AnalysisKind::MonoLayout(behavior_flag),
+                    format!(
+                        "Instantiation `{}` of `{}` performs {}",
+                        instance,
+                        tcx.def_path_str(def_id),
+                        status.conversions().join(", ")
+                    ),
+                    &color_span,
+                ))
+            }
+        }
+    }
+
+    /// Instances of local generic functions, ordered by their printed name for stable output
+    fn local_generic_instances(&self) -> Vec<Instance<'tcx>> {
+        let tcx = self.rcx.tcx();
+        let (_, codegen_units) = tcx.collect_and_partition_mono_items(());
+
+        let mut seen = HashSet::new();
+        let mut instances = Vec::new();
+        for cgu in codegen_units {
+            for item in cgu.items().keys() {
+                if let MonoItem::Fn(instance) = item
+                    && let InstanceDef::Item(def_id) = instance.def
+                    && def_id.is_local()
+                    && !instance.substs.is_empty()
+                    && seen.insert(*instance)
+                {
+                    instances.push(*instance);
+                }
+            }
+        }
+        // distinct instances may print alike, e.g. same-named types of two crates, so the name only orders them
+        instances.sort_by_cached_key(|instance| instance.to_string());
+        instances
+    }
+}
+
+mod inner {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    pub struct MonoLayoutStatus {
+        misaligned: Vec<Span>,
+        invalid_values: Vec<Span>,
+        unknown_layouts: Vec<Span>,
+        conversions: Vec<String>,
+        behavior_flag: BehaviorFlag,
+    }
+
+    impl MonoLayoutStatus {
+        pub fn behavior_flag(&self) -> BehaviorFlag {
+            self.behavior_flag
+        }
+
+        pub fn misaligned_spans(&self) -> &Vec<Span> {
+            &self.misaligned
+        }
+
+        pub fn invalid_value_spans(&self) -> &Vec<Span> {
+            &self.invalid_values
+        }
+
+        pub fn unknown_layout_spans(&self) -> &Vec<Span> {
+            &self.unknown_layouts
+        }
+
+        /// e.g. "misaligned cast `*const u8` -> `*const u32`"
+        pub fn conversions(&self) -> &Vec<String> {
+            &self.conversions
+        }
+    }
+
+    pub struct MonoLayoutBodyAnalyzer<'a, 'tcx> {
+        rcx: RuMorphCtxt<'a, 'tcx>,
+        body: &'a ir::Body<'tcx>,
+        instance: Instance<'tcx>,
+        status: MonoLayoutStatus,
+    }
+
+    impl<'a, 'tcx> MonoLayoutBodyAnalyzer<'a, 'tcx> {
+        pub fn analyze_instance(rcx: RuMorphCtxt<'_, 'tcx>, instance: Instance<'tcx>) -> Option<MonoLayoutStatus> {
+            match rcx.translate_body(instance.def_id()).as_ref() {
+                Err(e) => {
+                    // MIR is not available for def - log it and continue
+                    e.log();
+                    None
+                }
+                Ok(body) => {
+                    let body_analyzer = MonoLayoutBodyAnalyzer {
+                        rcx,
+                        body,
+                        instance,
+                        status: Default::default(),
+                    };
+                    Some(body_analyzer.analyze())
+                }
+            }
+        }
+
+        fn subst(&self, ty: Ty<'tcx>) -> Ty<'tcx> {
+            self.instance.subst_mir_and_normalize_erasing_regions(
+                self.rcx.tcx(),
+                ParamEnv::reveal_all(),
+                ty::EarlyBinder::bind(ty),
+            )
+        }
+
+        fn analyze(mut self) -> MonoLayoutStatus {
+            let tcx = self.rcx.tcx();
+
+            for (_location, statement) in self.body.statements() {
+                let (op, to_ty, conv) = match &statement.kind {
+                    StatementKind::Assign(box (_, Rvalue::Cast(CastKind::PtrToPtr, op, to_ty))) => (op, *to_ty, "cast"),
+                    StatementKind::Assign(box (_, Rvalue::Cast(CastKind::Transmute, op, to_ty))) => (op, *to_ty, "transmute"),
+                    _ => continue,
+                };
+                let from_ty = match op {
+                    Operand::Copy(place) | Operand::Move(place) => place.ty(self.body, tcx).ty,
+                    Operand::Constant(box cnst) => cnst.ty(),
+                };
+                // casts without type parameters are already decided by the other checkers
+                if !from_ty.has_param() && !to_ty.has_param() {
+                    continue;
+                }
+
+                let (from_ty, to_ty) = (self.subst(from_ty), self.subst(to_ty));
+                let span = statement.source_info.span;
+
+                let lc = LayoutChecker::new(self.rcx, ParamEnv::reveal_all(), from_ty, to_ty);
+                if let (true, true, Comparison::Less) =
+                    (lc.is_fty_layout_spec(), lc.is_tty_layout_spec(), lc.get_align_status())
+                {
+                    self.status.behavior_flag |= BehaviorFlag::MISALIGNED;
+                    self.status.misaligned.push(span);
+                    self.status
+                        .conversions
+                        .push(format!("misaligned {} `{}` -> `{}`{}", conv, from_ty, to_ty, lc.target_note()));
+                } else if let Comparison::Unknown = lc.get_align_status() {
+                    self.status.behavior_flag |= BehaviorFlag::UNKNOWN_LAYOUT;
+                    self.status.unknown_layouts.push(span);
+                    self.status
+                        .conversions
+                        .push(format!("unchecked {} `{}` -> `{}`{}", conv, from_ty, to_ty, lc.target_note()));
+                }
+
+                let (from_pointee, to_pointee) = (get_pointee(from_ty), get_pointee(to_ty));
+                let violated = invalid_values(tcx, from_pointee, to_pointee);
+                if !violated.is_empty() {
+                    self.status.behavior_flag |= BehaviorFlag::INVALID_VALUE;
+                    self.status.invalid_values.push(span);
+                    let note = validity::note(&violated);
+                    self.status
+                        .conversions
+                        .push(format!("invalid-value {} `{}` -> `{}`{}", conv, from_ty, to_ty, note));
+                }
+            }
+
+            self.status
+        }
+    }
+}
+
+/// Restrictions of `to_ty` that reading a `from_ty` as a `to_ty` can violate;
+/// empty if either layout is unknown, which is flagged as an unknown layout instead
+fn invalid_values<'tcx>(tcx: TyCtxt<'tcx>, from_ty: Ty<'tcx>, to_ty: Ty<'tcx>) -> Vec<Restriction<'tcx>> {
+    if from_ty == to_ty || from_ty.contains(to_ty) {
+        return Vec::new();
+    }
+    violated_restrictions(tcx, ParamEnv::reveal_all(), from_ty, to_ty).unwrap_or_default()
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct BehaviorFlag: u16 {
+        const MISALIGNED = 0b00000001;
+        const INVALID_VALUE = 0b00000010;
+        const UNKNOWN_LAYOUT = 0b00000100;
+    }
+}
+
+impl IntoReportLevel for BehaviorFlag {
+    fn report_level(&self, _visibility: bool) -> ReportLevel {
+        // a conversion whose layout can't be computed is only worth a look
+        if *self == BehaviorFlag::UNKNOWN_LAYOUT {
+            ReportLevel::Info
+        } else {
+            ReportLevel::Error
+        }
+    }
+}
@@ -12,8 +12,12 @@ use crate::graph::GraphTaint;
 use crate::prelude::*;
 use crate::{
     analysis::{AnalysisKind, IntoReportLevel, LayoutChecker, Comparison},
-    graph::TaintAnalyzer,
+    dataflow::body_taint_analyzer,
+    graph::TaintEngine,
+    guard::GuardFacts,
+    interval::IntervalAnalysis,
     ir,
+    provenance::{Origin, Traced},
     paths::{self, *},
     report::{Report, ReportLevel},
     utils,
@@ -40,12 +44,12 @@ impl AnalysisError for OverflowError {
     }
 }
 
-pub struct OverflowChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct OverflowChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
 }
 
-impl<'tcx> OverflowChecker<'tcx> {
-    pub fn new(rcx: RuMorphCtxt<'tcx>) -> Self {
+impl<'a, 'tcx> OverflowChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
         OverflowChecker { rcx }
     }
 
@@ -93,16 +97,21 @@ impl<'tcx> OverflowChecker<'tcx> {
                         color_span.add_sub_span(Color::Green, span);
                     }
 
-                    rumorph_report(Report::with_color_span(
+                    let mut message = format!(
+                        "Potential overflow issue in `{}` with Pattern `{}` at line `{}`",
+                        tcx.def_path_str(hir_map.body_owner_def_id(body_id).to_def_id()),
+                        err,
+                        lc
+                    );
+                    if let Some(provenance) = status.provenance() {
+                        message.push_str(&format!(": {}", provenance));
+                    }
+
+                    self.rcx.report(Report::with_color_span(
                         tcx,
                         behavior_flag.report_level(true),
                         AnalysisKind::Overflow(behavior_flag),
-                        format!(
-                            "Potential overflow issue in `{}` with Pattern `{}` at line `{}`",
-                            tcx.def_path_str(hir_map.body_owner_def_id(body_id).to_def_id()),
-                            err,
-                            lc
-                        ),
+                        message,
                         &color_span,
                     ))
                 } else {
@@ -126,6 +135,8 @@ mod inner {
         behavior_flag: BehaviorFlag,
         error: usize,
         loc: usize,
+        /// e.g. "argument `amount` flows into subtraction at line 4"
+        provenance: Option<String>,
     }
 
     impl OverflowStatus {
@@ -161,17 +172,21 @@ mod inner {
         pub fn get_error_loc(&self) -> usize {
             self.loc
         }
+
+        pub fn provenance(&self) -> Option<&str> {
+            self.provenance.as_deref()
+        }
     }
 
     pub struct OverflowBodyAnalyzer<'a, 'tcx> {
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'a, 'tcx>,
         body: &'a ir::Body<'tcx>,
         param_env: ParamEnv<'tcx>,
         status: OverflowStatus,
     }
 
     impl<'a, 'tcx> OverflowBodyAnalyzer<'a, 'tcx> {
-        fn new(rcx: RuMorphCtxt<'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        fn new(rcx: RuMorphCtxt<'a, 'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
             OverflowBodyAnalyzer {
                 rcx,
                 body,
@@ -180,7 +195,7 @@ mod inner {
             }
         }
 
-        pub fn analyze_body(rcx: RuMorphCtxt<'tcx>, body_id: BodyId) -> Option<OverflowStatus> {
+        pub fn analyze_body(rcx: RuMorphCtxt<'_, 'tcx>, body_id: BodyId) -> Option<OverflowStatus> {
             let hir_map = rcx.tcx().hir();
             let body_did = hir_map.body_owner_def_id(body_id).to_def_id();
 
@@ -209,30 +224,33 @@ mod inner {
         }
 
         fn analyze(mut self) -> OverflowStatus {
-            let mut taint_analyzer = TaintAnalyzer::new(self.body);
+            let mut taint_analyzer = body_taint_analyzer(self.rcx, self.body);
+            let guards = GuardFacts::new(self.rcx.tcx(), self.body);
+            let intervals = IntervalAnalysis::new(self.rcx.tcx(), self.body);
             // use `tainted_source` to maintain tainted external function args
             let mut tainted_source = Vec::new();
 
             let mut error_kind_map = HashMap::new();
             let mut sink_loc_map = HashMap::new();
+            // what each sink does, for the provenance of the report
+            let mut sink_operation_map = HashMap::new();
 
             // mark all the arguments as taint source
             for arg_idx in 1usize..self.body.original.arg_count + 1 {
                 // progress_info!("external as source: {:?}", arg_idx);
                 tainted_source.push(arg_idx);
-                taint_analyzer.mark_source(arg_idx, &BehaviorFlag::EXTERNAL);
+                taint_analyzer.mark_source(arg_idx, &Traced::new(BehaviorFlag::EXTERNAL, Origin::Arg(arg_idx)));
             }
 
-            for statement in self.body.statements() {
+            for (location, statement) in self.body.statements() {
                 // statement here is mir::Statement without translation
                 // while iterating statements, we plan to mark ty conv as source / plain deref as sink
-                let sp = statement.source_info.span;
-                let sm = self.rcx.tcx().sess.source_map();
-                let loc = sm.lookup_char_pos(sp.lo()).line;
-                match statement.kind {
+                let loc = self.body.line_of(location);
+                match &statement.kind {
                     StatementKind::Assign(box (lplace, rval)) => {
                         match rval {
                             Rvalue::Cast(cast_kind, op, to_ty) => {
+                                let to_ty = *to_ty;
                                 match cast_kind {
                                     CastKind::IntToInt | CastKind::FloatToInt | CastKind::FloatToFloat | CastKind::IntToFloat | CastKind::Transmute => {
                                         let f_ty = get_ty_from_op(self.body, self.rcx, &op);
@@ -244,15 +262,17 @@ mod inner {
                                                 let pl = get_place_from_op(&op);
                                                 match pl {
                                                     Ok(place) => {
-                                                        let id = place.local.index();
+                                                        let id = self.body.place_node(&place);
 
                                                         // if A's align < B's align, taint as source
                                                         match align_status {
-                                                            Comparison::Greater => {
-                                                                let id2 = lplace.local.index();
+                                                            // a value that provably fits, e.g. `x & 0xff` as `u8`, is kept intact
+                                                            Comparison::Greater if !intervals.fits(location, op, to_ty) => {
+                                                                let id2 = self.body.place_node(&lplace);
                                                                 taint_analyzer.mark_sink(id2);
                                                                 error_kind_map.insert(id2, "downcast");
                                                                 sink_loc_map.insert(id2, loc);
+                                                                sink_operation_map.insert(id2, "a narrowing cast");
                                                                 self.status
                                                                     .ty_convs
                                                                     .push(statement.source_info.span);
@@ -297,10 +317,19 @@ mod inner {
                                                 }
                                             }
                                         }
-                                        let idx = lplace.local.index();
+                                        // `a - b` under `if a >= b` cannot underflow
+                                        if *op == BinOp::Sub && guards.holds(location, op1, BinOp::Ge, op2) {
+                                            continue;
+                                        }
+                                        // the ranges of the operands keep the result within the type
+                                        if intervals.cannot_overflow(location, *op, op1, op2) {
+                                            continue;
+                                        }
+                                        let idx = self.body.place_node(&lplace);
                                         taint_analyzer.mark_sink(idx);
                                         error_kind_map.insert(idx, "unsafeop");
                                         sink_loc_map.insert(idx, loc);
+                                        sink_operation_map.insert(idx, operation_name(*op));
                                         self.status
                                             .ty_convs
                                             .push(statement.source_info.span);
@@ -315,10 +344,8 @@ mod inner {
                 }
             }
 
-            for (_id, terminator) in self.body.terminators().enumerate() {
-                let sp = terminator.original.source_info.span;
-                let sm = self.rcx.tcx().sess.source_map();
-                let loc = sm.lookup_char_pos(sp.lo()).line;
+            for (bb, terminator) in self.body.located_terminators() {
+                let loc = self.body.terminator_line(bb);
                 match &terminator.kind {
                     ir::TerminatorKind::StaticCall {
                         callee_did,
@@ -335,10 +362,11 @@ mod inner {
                         // let z = x.pow(y);
                         // we will taint z as sink
                         if sym.contains("pow") && !sym.contains("checked_") {
-                            let id = dest.local.index();
+                            let id = self.body.place_node(&dest);
                             taint_analyzer.mark_sink(id);
                             error_kind_map.insert(id, "unsafeopcall");
                             sink_loc_map.insert(id, loc);
+                            sink_operation_map.insert(id, "`pow`");
                         }
                     },
                     ir::TerminatorKind::SwitchInt {
@@ -352,8 +380,9 @@ mod inner {
             }
 
             let prog_flag = taint_analyzer.propagate();
+            self.rcx.dump_graphs("Overflow", self.body, &*taint_analyzer);
             // println!("{:?}", prog_flag);
-            self.status.behavior_flag = prog_flag;
+            self.status.behavior_flag = prog_flag.taint;
 
             // there are two kinds of error stored in error_kind_map: downcast, unsafeop, unsafeopcall
             for sink in taint_analyzer.get_reachable_sinks() {
@@ -373,13 +402,23 @@ mod inner {
                     Some(lc) => *lc,
                     _ => 0,
                 };
+                self.status.provenance = taint_analyzer.taint_state()[*sink]
+                    .describe(self.rcx.tcx(), self.body)
+                    .map(|origins| {
+                        format!(
+                            "{} flows into {} at line {}",
+                            origins,
+                            sink_operation_map.get(sink).copied().unwrap_or("the sink"),
+                            self.status.loc
+                        )
+                    });
             }
 
             self.status
         }
     }
 
-    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'tcx>, body_def_id: DefId) {
+    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, body_def_id: DefId) {
         warn!("Paths discovery function has been detected");
         if let Ok(body) = rcx.translate_body(body_def_id).as_ref() {
             for terminator in body.terminators() {
@@ -420,6 +459,16 @@ fn is_ptr_ty<'tcx>(from_ty: Ty<'tcx>, to_ty: Ty<'tcx>) -> bool {
     (is_fty_ptr & is_tty_ptr)
 }
 
+fn operation_name(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "addition",
+        BinOp::Sub => "subtraction",
+        BinOp::Mul => "multiplication",
+        BinOp::Div => "division",
+        _ => "an arithmetic operation",
+    }
+}
+
 fn get_place_from_op<'tcx>(op: &Operand<'tcx>) -> Result<Place<'tcx>, &'static str> {
     match op {
         Operand::Copy(place) | Operand::Move(place) => {
@@ -429,7 +478,7 @@ fn get_place_from_op<'tcx>(op: &Operand<'tcx>) -> Result<Place<'tcx>, &'static s
     }
 }
 
-fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
+fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'_, 'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
     match op {
         Operand::Copy(place) | Operand::Move(place) => {
             Ok(place.ty(bd, rcx.tcx()).ty)
@@ -482,4 +531,8 @@ impl GraphTaint for BehaviorFlag {
     fn join(&mut self, taint: &Self) {
         *self |= *taint;
     }
+
+    fn remove(&mut self, taint: &Self) {
+        *self &= !*taint;
+    }
 }
@@ -12,7 +12,9 @@ use crate::graph::GraphTaint;
 use crate::prelude::*;
 use crate::{
     analysis::{AnalysisKind, IntoReportLevel, LayoutChecker, Comparison},
-    graph::TaintAnalyzer,
+    dataflow::body_taint_analyzer,
+    graph::TaintEngine,
+    guard::GuardFacts,
     ir,
     paths::{self, *},
     report::{Report, ReportLevel},
@@ -40,12 +42,12 @@ impl AnalysisError for TruncationError {
     }
 }
 
-pub struct TruncationChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct TruncationChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
 }
 
-impl<'tcx> TruncationChecker<'tcx> {
-    pub fn new(rcx: RuMorphCtxt<'tcx>) -> Self {
+impl<'a, 'tcx> TruncationChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
         TruncationChecker { rcx }
     }
 
@@ -93,15 +95,26 @@ impl<'tcx> TruncationChecker<'tcx> {
                         color_span.add_sub_span(Color::Green, span);
                     }
 
-                    rumorph_report(Report::with_color_span(
+                    for &span in status.slice_spans() {
+                        color_span.add_sub_span(Color::Magenta, span);
+                    }
+
+                    let def_id = hir_map.body_owner_def_id(body_id).to_def_id();
+                    let reachability = self
+                        .rcx
+                        .public_entry_description(def_id)
+                        .map(|description| format!(" ({})", description))
+                        .unwrap_or_default();
+                    self.rcx.report(Report::with_color_span(
                         tcx,
                         behavior_flag.report_level(true),
                         AnalysisKind::Truncation(behavior_flag),
                         format!(
-                            "Potential Truncation issue in `{}` with Pattern `{}` at line `{}`",
-                            tcx.def_path_str(hir_map.body_owner_def_id(body_id).to_def_id()),
+                            "Potential Truncation issue in `{}` with Pattern `{}` at line `{}`{}",
+                            tcx.def_path_str(def_id),
                             err,
-                            lc
+                            lc,
+                            reachability
                         ),
                         &color_span,
                     ))
@@ -123,6 +136,8 @@ mod inner {
         plain_deref: Vec<Span>,
         unresolvable_generic_functions: Vec<Span>,
         ty_convs: Vec<Span>,
+        /// statements the reported sink depends on
+        slice: Vec<Span>,
         behavior_flag: BehaviorFlag,
         error: usize,
         loc: usize,
@@ -154,6 +169,10 @@ mod inner {
             &self.ty_convs
         }
 
+        pub fn slice_spans(&self) -> &Vec<Span> {
+            &self.slice
+        }
+
         pub fn error_kind(&self) -> usize {
             self.error
         }
@@ -164,14 +183,14 @@ mod inner {
     }
 
     pub struct TruncationBodyAnalyzer<'a, 'tcx> {
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'a, 'tcx>,
         body: &'a ir::Body<'tcx>,
         param_env: ParamEnv<'tcx>,
         status: TruncationStatus,
     }
 
     impl<'a, 'tcx> TruncationBodyAnalyzer<'a, 'tcx> {
-        fn new(rcx: RuMorphCtxt<'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        fn new(rcx: RuMorphCtxt<'a, 'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
             TruncationBodyAnalyzer {
                 rcx,
                 body,
@@ -180,7 +199,7 @@ mod inner {
             }
         }
 
-        pub fn analyze_body(rcx: RuMorphCtxt<'tcx>, body_id: BodyId) -> Option<TruncationStatus> {
+        pub fn analyze_body(rcx: RuMorphCtxt<'_, 'tcx>, body_id: BodyId) -> Option<TruncationStatus> {
             let hir_map = rcx.tcx().hir();
             let body_did = hir_map.body_owner_def_id(body_id).to_def_id();
 
@@ -209,7 +228,8 @@ mod inner {
         }
 
         fn analyze(mut self) -> TruncationStatus {
-            let mut taint_analyzer = TaintAnalyzer::new(self.body);
+            let mut taint_analyzer = body_taint_analyzer(self.rcx, self.body);
+            let guards = GuardFacts::new(self.rcx.tcx(), self.body);
             // use `tainted_source` to maintain tainted external function args
             let mut tainted_source = Vec::new();
             let mut place_size = HashMap::new();
@@ -238,9 +258,9 @@ mod inner {
                 }
             }
 
-            for statement in self.body.statements() {
+            for (_location, statement) in self.body.statements() {
                 progress_info!("kind: {:?} -> {:?}", statement.kind, statement);
-                match statement.kind {
+                match &statement.kind {
                     StatementKind::Assign(box (lplace, rval)) => {
                         match rval {
                             Rvalue::Use(op) => {
@@ -251,8 +271,8 @@ mod inner {
                             },
                             Rvalue::Aggregate(box aggregate_kind, operands) => {
                                 if let AggregateKind::Adt(def_id, variant_idx, _, _, _) = aggregate_kind {
-                                    if is_range_full(self.rcx, def_id) {
-                                        rangefull_place = lplace.local.index();
+                                    if is_range_full(self.rcx, *def_id) {
+                                        rangefull_place = self.body.place_node(&lplace);
                                     }
                                 }
                             },
@@ -263,10 +283,8 @@ mod inner {
                 }
             }
 
-            for (_id, terminator) in self.body.terminators().enumerate() {
-                let sp = terminator.original.source_info.span;
-                let sm = self.rcx.tcx().sess.source_map();
-                let loc = sm.lookup_char_pos(sp.lo()).line;
+            for (bb, terminator) in self.body.located_terminators() {
+                let loc = self.body.terminator_line(bb);
                 match &terminator.kind {
                     ir::TerminatorKind::StaticCall {
                         callee_did,
@@ -286,10 +304,16 @@ mod inner {
                         // requires the source and dest buffer have the same size
                         // to make sure whether the original size of source buffer is larger than dest buffer
                         // we need to detect whether source buffer is sliced
+                        // integer ranges (see `interval`) don't decide this sink: a mismatch is
+                        // between two slice lengths, which only the guard facts relate
                         if sym.contains("copy_") {
                             if let [dst, src] = &args[..] {
+                                // an explicit `src.len() == dst.len()` check makes the copy safe
+                                if guards.lengths_equal(self.body.terminator_loc(bb), dst, src) {
+                                    continue;
+                                }
                                 if let Some(src_place) = src.place() {
-                                    let src_idx = src_place.local.index();                                        
+                                    let src_idx = self.body.place_node(&src_place);                                        
                                     if sliced_array_place != 0 && taint_analyzer.is_reachable(sliced_array_place, src_idx) {
                                         progress_info!("ts idx: {:?} -> src idx: {:?}", sliced_array_place, src_idx);
                                         // if let (src_ty, dst_ty) = (src.ty(&self.body.original, self.rcx.tcx()), dst.ty(&self.body.original, self.rcx.tcx())) {
@@ -318,17 +342,13 @@ mod inner {
                             }                            
                         } else if sym.contains("index") {
                             if let [buf, idx] = &args[..] {
-                                for ts in &tainted_source {
-                                    if let Some(buf_place) = buf.place() {
-                                        let buf_idx = buf_place.local.index();
-                                        if let Some(idx_place) = idx.place() {
-                                            let idx = idx_place.local.index();
-                                            if taint_analyzer.is_reachable(*ts, buf_idx) {
-                                                if !taint_analyzer.is_reachable(rangefull_place, idx) && rangefull_place != 0 {
-                                                    progress_info!("ts idx: {:?} -> buf idx: {:?}", *ts, buf_idx);
-                                                    sliced_array_place = buf_idx;   
-                                                }                                  
-                                            }
+                                if let (Some(buf_place), Some(idx_place)) = (buf.place(), idx.place()) {
+                                    let buf_idx = self.body.place_node(&buf_place);
+                                    let idx = self.body.place_node(&idx_place);
+                                    if taint_analyzer.is_reachable_from_any(&tainted_source, buf_idx) {
+                                        if !taint_analyzer.is_reachable(rangefull_place, idx) && rangefull_place != 0 {
+                                            progress_info!("tainted source -> buf idx: {:?}", buf_idx);
+                                            sliced_array_place = buf_idx;
                                         }
                                     }
                                 }
@@ -340,6 +360,7 @@ mod inner {
             }
 
             let prog_flag = taint_analyzer.propagate();
+            self.rcx.dump_graphs("Truncation", self.body, &*taint_analyzer);
             // println!("{:?}", prog_flag);
             self.status.behavior_flag = prog_flag;
 
@@ -359,13 +380,19 @@ mod inner {
                     Some(lc) => *lc,
                     _ => 0,
                 };
+                self.status.slice = self
+                    .body
+                    .backward_slice(*sink)
+                    .into_iter()
+                    .map(|location| self.body.source_info(location).span)
+                    .collect();
             }
 
             self.status
         }
     }
 
-    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'tcx>, body_def_id: DefId) {
+    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, body_def_id: DefId) {
         warn!("Paths discovery function has been detected");
         if let Ok(body) = rcx.translate_body(body_def_id).as_ref() {
             for terminator in body.terminators() {
@@ -386,7 +413,7 @@ mod inner {
     }
 }
 
-fn is_range_full<'tcx>(rcx: RuMorphCtxt<'tcx>, def_id: DefId) -> bool {
+fn is_range_full<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, def_id: DefId) -> bool {
     let range_full = rcx.tcx().lang_items().range_full_struct();
     if let Some(range_full_id) = range_full {
         def_id == range_full_id
@@ -449,4 +476,8 @@ impl GraphTaint for BehaviorFlag {
     fn join(&mut self, taint: &Self) {
         *self |= *taint;
     }
+
+    fn remove(&mut self, taint: &Self) {
+        *self &= !*taint;
+    }
 }
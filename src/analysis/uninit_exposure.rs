@@ -1,4 +1,4 @@
-use rustc_hir::{def_id::DefId, BodyId, Unsafety};
+use rustc_hir::{def_id::DefId, BodyId};
 use rustc_middle::mir::{Operand, StatementKind, Rvalue, CastKind, Place, HasLocalDecls, AggregateKind};
 use rustc_middle::mir::RETURN_PLACE;
 use rustc_middle::ty::{self, Ty, Instance, ParamEnv, TyKind};
@@ -11,12 +11,14 @@ use crate::graph::GraphTaint;
 use crate::prelude::*;
 use crate::{
     analysis::{AnalysisKind, IntoReportLevel, LayoutChecker, Comparison},
-    graph::TaintAnalyzer,
+    dataflow::body_taint_analyzer,
+    graph::TaintEngine,
     ir,
     paths::{self, *},
     report::{Report, ReportLevel},
     utils,
     visitor::ContainsUnsafe,
+    marker::Guarantee,
     context::RuMorphCtxt,
     progress_info,
 };
@@ -39,12 +41,12 @@ impl AnalysisError for UninitExposureError {
     }
 }
 
-pub struct UninitExposureChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct UninitExposureChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
 }
 
-impl<'tcx> UninitExposureChecker<'tcx> {
-    pub fn new(rcx: RuMorphCtxt<'tcx>) -> Self {
+impl<'a, 'tcx> UninitExposureChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
         UninitExposureChecker { rcx }
     }
 
@@ -95,7 +97,7 @@ impl<'tcx> UninitExposureChecker<'tcx> {
                         color_span.add_sub_span(Color::Cyan, span);
                     }
 
-                    rumorph_report(Report::with_color_span(
+                    self.rcx.report(Report::with_color_span(
                         tcx,
                         behavior_flag.report_level(visible),
                         AnalysisKind::UninitExposure(behavior_flag),
@@ -158,14 +160,14 @@ mod inner {
     }
 
     pub struct UninitExposureBodyAnalyzer<'a, 'tcx> {
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'a, 'tcx>,
         body: &'a ir::Body<'tcx>,
         param_env: ParamEnv<'tcx>,
         status: UninitExposureStatus,
     }
 
     impl<'a, 'tcx> UninitExposureBodyAnalyzer<'a, 'tcx> {
-        fn new(rcx: RuMorphCtxt<'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        fn new(rcx: RuMorphCtxt<'a, 'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
             UninitExposureBodyAnalyzer {
                 rcx,
                 body,
@@ -174,7 +176,7 @@ mod inner {
             }
         }
 
-        pub fn analyze_body(rcx: RuMorphCtxt<'tcx>, body_id: BodyId) -> Option<UninitExposureStatus> {
+        pub fn analyze_body(rcx: RuMorphCtxt<'_, 'tcx>, body_id: BodyId) -> Option<UninitExposureStatus> {
             let hir_map = rcx.tcx().hir();
             let body_did = hir_map.body_owner_def_id(body_id).to_def_id();
 
@@ -187,8 +189,7 @@ mod inner {
                 trace_calls_in_body(rcx, body_did);
                 None
             } else if ContainsUnsafe::contains_unsafe(rcx.tcx(), body_id) {
-                let fn_sig = rcx.tcx().fn_sig(body_did).skip_binder();
-                if let Unsafety::Unsafe = fn_sig.unsafety() {
+                if utils::is_unsafe_fn(rcx.tcx(), body_did) {
                     progress_info!("The function is unsafe");
                     None
                 } else {
@@ -214,20 +215,20 @@ mod inner {
         }
 
         fn analyze(mut self) -> UninitExposureStatus {
-            let mut taint_analyzer = TaintAnalyzer::new(self.body);
+            let mut taint_analyzer = body_taint_analyzer(self.rcx, self.body);
 
             let tcx = self.rcx.tcx();
 
-            for statement in self.body.statements() {
+            for (_location, statement) in self.body.statements() {
                 // statement here is mir::Statement without translation
                 // while iterating statements, we plan to mark ty conv as source / plain deref as sink
-                match statement.kind {
+                match &statement.kind {
                     StatementKind::Assign(box (lplace, rval)) => {
                         // lhs could also contains deref operation
                         if lplace.is_indirect() {
                             // contains deref projection
                             // progress_info!("warn::deref on place:{}", lplace.local.index());
-                            taint_analyzer.mark_sink(lplace.local.index());
+                            taint_analyzer.mark_sinks(self.body.read_nodes(&lplace));
                             self.status
                                 .plain_deref
                                 .push(statement.source_info.span);
@@ -235,6 +236,7 @@ mod inner {
                         // rhs
                         match rval {
                             Rvalue::Cast(cast_kind, op, to_ty) => {
+                                let to_ty = *to_ty;
                                 match cast_kind {
                                     CastKind::PtrToPtr => {
                                         progress_info!("cast::ptr-ptr");
@@ -251,7 +253,7 @@ mod inner {
                                                 let pl = get_place_from_op(&op);
                                                 match pl {
                                                     Ok(place) => {
-                                                        let id = place.local.index();
+                                                        let id = self.body.place_node(&place);
 
                                                         // check
                                                         // (gen > prim) (adt > prim)
@@ -270,7 +272,8 @@ mod inner {
                                                         if is_from_gen == true && is_to_gen == false && tty.to_string() != "usize" && !tty.is_c_void(tcx) && !tty.contains(fty) {
                                                             // generic > concrete
                                                             // call TraitChecker for help
-                                                            if ty_bnd.len() == 0 {
+                                                            // a bound like `T: NoUninit` rules out padding bytes
+                                                            if ty_bnd.len() == 0 && !lc.from_guarantee().contains(Guarantee::NO_PADDING) {
                                                                 // it could be arbitrary type
                                                                 if is_to_prime | is_to_arr_slice {
                                                                     // progress_info!("warn::cast (gen>prime/arr/slice) from id{} to lplace{}", id, lplace.local.index());
@@ -348,7 +351,7 @@ mod inner {
                                                 let pl = get_place_from_op(&op);
                                                 match pl {
                                                     Ok(place) => {
-                                                        let id = place.local.index();
+                                                        let id = self.body.place_node(&place);
 
                                                         // check
                                                         // (gen > prim) (adt > prim)
@@ -367,7 +370,8 @@ mod inner {
                                                         if is_from_gen == true && is_to_gen == false && tty.to_string() != "usize" && !tty.is_c_void(tcx) && !tty.contains(fty) {
                                                             // generic > concrete
                                                             // call TraitChecker for help
-                                                            if ty_bnd.len() == 0 {
+                                                            // a bound like `T: NoUninit` rules out padding bytes
+                                                            if ty_bnd.len() == 0 && !lc.from_guarantee().contains(Guarantee::NO_PADDING) {
                                                                 // it could be arbitrary type
                                                                 if is_to_prime | is_to_arr_slice {
                                                                     // progress_info!("warn::transmute (gen>prime/arr/slice) from id{} to lplace{}", id, lplace.local.index());
@@ -448,12 +452,12 @@ mod inner {
                             | Rvalue::ShallowInitBox(op, _) => {
                                 match op {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
+                                        let ids = self.body.read_nodes(&pl);
                                         // progress_info!("[dbg] lplace: {}, rplace: {}", lplace.local.index(), pl.local.index());
                                         if pl.is_indirect() {
                                             // contains deref projection
                                             // progress_info!("warn::deref on place:{}", id);
-                                            taint_analyzer.mark_sink(id);
+                                            taint_analyzer.mark_sinks(ids);
                                             self.status
                                                 .plain_deref
                                                 .push(statement.source_info.span);
@@ -467,11 +471,11 @@ mod inner {
                             | Rvalue::Len(pl)
                             | Rvalue::Discriminant(pl)
                             | Rvalue::CopyForDeref(pl) => {
-                                let id = pl.local.index();
+                                let ids = self.body.read_nodes(&pl);
                                 if pl.is_indirect() {
                                     // contains deref projection
                                     // progress_info!("warn::deref on place:{}", id);
-                                    taint_analyzer.mark_sink(id);
+                                    taint_analyzer.mark_sinks(ids);
                                     self.status
                                         .plain_deref
                                         .push(statement.source_info.span);
@@ -499,9 +503,20 @@ mod inner {
                         // Check for lifetime bypass
                         let symbol_vec = ext.get_def_path(callee_did);
                         let sym = symbol_vec[ symbol_vec.len() - 1 ].as_str();
+                        // a local callee dereferencing an argument counts as a deref at the call site
+                        if let Some(summary) = terminator.static_callee().and_then(|callee| self.rcx.summary(callee)) {
+                            for &arg in &summary.deref_args {
+                                if let Some(pl) = args.get(arg).and_then(|arg| arg.place()) {
+                                    taint_analyzer.mark_sinks(self.body.read_nodes(&pl));
+                                    self.status
+                                        .plain_deref
+                                        .push(terminator.original.source_info.span);
+                                }
+                            }
+                        }
                         if sym.contains("write_unaligned") {
                             // core::ptr::write_unaligned is used to init uninitialized memory
-                            let id = dest.local.index();
+                            let id = self.body.place_node(&dest);
                             taint_analyzer
                                 .clear_source(id);
                         } else if sym.contains("read_unaligned") {
@@ -509,8 +524,8 @@ mod inner {
                             for arg in args {
                                 match arg {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
-                                        taint_analyzer.mark_sink(id);
+                                        let ids = self.body.read_nodes(&pl);
+                                        taint_analyzer.mark_sinks(ids);
                                         self.status
                                             .access_uninit
                                             .push(terminator.original.source_info.span);
@@ -524,8 +539,8 @@ mod inner {
                                 // arg: mir::Operand
                                 match arg {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
-                                        taint_analyzer.mark_sink(id);
+                                        let ids = self.body.read_nodes(&pl);
+                                        taint_analyzer.mark_sinks(ids);
                                         self.status
                                             .strong_bypasses
                                             .push(terminator.original.source_info.span);
@@ -539,8 +554,8 @@ mod inner {
                                 // arg: mir::Operand
                                 match arg {
                                     Operand::Copy(pl) | Operand::Move(pl) => {
-                                        let id = pl.local.index();
-                                        taint_analyzer.mark_sink(id);
+                                        let ids = self.body.read_nodes(&pl);
+                                        taint_analyzer.mark_sinks(ids);
                                         self.status
                                             .weak_bypasses
                                             .push(terminator.original.source_info.span);
@@ -570,12 +585,13 @@ mod inner {
             }
 
             self.status.behavior_flag = taint_analyzer.propagate();
+            self.rcx.dump_graphs("UninitExposure", self.body, &*taint_analyzer);
             self.status
         }
 
     }
 
-    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'tcx>, body_def_id: DefId) {
+    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, body_def_id: DefId) {
         warn!("Paths discovery function has been detected");
         if let Ok(body) = rcx.translate_body(body_def_id).as_ref() {
             for terminator in body.terminators() {
@@ -625,7 +641,7 @@ fn get_place_from_op<'tcx>(op: &Operand<'tcx>) -> Result<Place<'tcx>, &'static s
     }
 }
 
-fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
+fn get_ty_from_op<'tcx>(bd: &ir::Body<'tcx>, rcx: RuMorphCtxt<'_, 'tcx>, op: &Operand<'tcx>) -> Result<Ty<'tcx>, &'static str> {
     match op {
         Operand::Copy(place) | Operand::Move(place) => {
             Ok(place.ty(bd, rcx.tcx()).ty)
@@ -682,4 +698,8 @@ impl GraphTaint for BehaviorFlag {
     fn join(&mut self, taint: &Self) {
         *self |= *taint;
     }
+
+    fn remove(&mut self, taint: &Self) {
+        *self &= !*taint;
+    }
 }
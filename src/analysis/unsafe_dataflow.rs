@@ -10,7 +10,8 @@ use crate::graph::GraphTaint;
 use crate::prelude::*;
 use crate::{
     analysis::{AnalysisKind, IntoReportLevel},
-    graph::TaintAnalyzer,
+    dataflow::body_taint_analyzer,
+    graph::TaintEngine,
     ir,
     paths::{self, *},
     report::{Report, ReportLevel},
@@ -36,12 +37,12 @@ impl AnalysisError for UnsafeDataflowError {
     }
 }
 
-pub struct UnsafeDataflowChecker<'tcx> {
-    rcx: RuMorphCtxt<'tcx>,
+pub struct UnsafeDataflowChecker<'a, 'tcx> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
 }
 
-impl<'tcx> UnsafeDataflowChecker<'tcx> {
-    pub fn new(rcx: RuMorphCtxt<'tcx>) -> Self {
+impl<'a, 'tcx> UnsafeDataflowChecker<'a, 'tcx> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>) -> Self {
         UnsafeDataflowChecker { rcx }
     }
 
@@ -73,7 +74,7 @@ impl<'tcx> UnsafeDataflowChecker<'tcx> {
                         color_span.add_sub_span(Color::Cyan, span);
                     }
 
-                    rumorph_report(Report::with_color_span(
+                    self.rcx.report(Report::with_color_span(
                         tcx,
                         behavior_flag.report_level(true),
                         AnalysisKind::UnsafeDataflow(behavior_flag),
@@ -119,14 +120,14 @@ mod inner {
     }
 
     pub struct UnsafeDataflowBodyAnalyzer<'a, 'tcx> {
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'a, 'tcx>,
         body: &'a ir::Body<'tcx>,
         param_env: ParamEnv<'tcx>,
         status: UnsafeDataflowStatus,
     }
 
     impl<'a, 'tcx> UnsafeDataflowBodyAnalyzer<'a, 'tcx> {
-        fn new(rcx: RuMorphCtxt<'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        fn new(rcx: RuMorphCtxt<'a, 'tcx>, param_env: ParamEnv<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
             UnsafeDataflowBodyAnalyzer {
                 rcx,
                 body,
@@ -135,7 +136,7 @@ mod inner {
             }
         }
 
-        pub fn analyze_body(rcx: RuMorphCtxt<'tcx>, body_id: BodyId) -> Option<UnsafeDataflowStatus> {
+        pub fn analyze_body(rcx: RuMorphCtxt<'_, 'tcx>, body_id: BodyId) -> Option<UnsafeDataflowStatus> {
             let hir_map = rcx.tcx().hir();
             let body_did = hir_map.body_owner_def_id(body_id).to_def_id();
 
@@ -167,7 +168,7 @@ mod inner {
         }
 
         fn analyze(mut self) -> UnsafeDataflowStatus {
-            let mut taint_analyzer = TaintAnalyzer::new(self.body);
+            let mut taint_analyzer = body_taint_analyzer(self.rcx, self.body);
 
             for (id, terminator) in self.body.terminators().enumerate() {
                 match terminator.kind {
@@ -251,6 +252,7 @@ mod inner {
             }
 
             self.status.behavior_flag = taint_analyzer.propagate();
+            self.rcx.dump_graphs("UnsafeDataflow", self.body, &*taint_analyzer);
             self.status
         }
 
@@ -284,7 +286,7 @@ mod inner {
         }
     }
 
-    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'tcx>, body_def_id: DefId) {
+    fn trace_calls_in_body<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, body_def_id: DefId) {
         warn!("Paths discovery function has been detected");
         if let Ok(body) = rcx.translate_body(body_def_id).as_ref() {
             for terminator in body.terminators() {
@@ -306,7 +308,7 @@ mod inner {
 
     // Check if the argument of `Vec::set_len()` is 0_usize.
     fn vec_set_len_to_0<'tcx>(
-        rcx: RuMorphCtxt<'tcx>,
+        rcx: RuMorphCtxt<'_, 'tcx>,
         callee_did: DefId,
         args: &Vec<Operand<'tcx>>,
     ) -> bool {
@@ -375,4 +377,8 @@ impl GraphTaint for BehaviorFlag {
     fn join(&mut self, taint: &Self) {
         *self |= *taint;
     }
+
+    fn remove(&mut self, taint: &Self) {
+        *self &= !*taint;
+    }
 }
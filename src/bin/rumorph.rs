@@ -3,17 +3,21 @@
 extern crate rustc_driver;
 extern crate rustc_errors;
 extern crate rustc_interface;
+extern crate rustc_target;
 
 #[macro_use]
 extern crate log;
 
 use std::env;
+use std::path::PathBuf;
 
 use rustc_driver::Compilation;
 use rustc_interface::{interface::Compiler, Queries};
+use rustc_target::spec::TargetTriple;
 
 // to use lib crate from bin crate, use crate name rather than `crate`
 use rumorph::log::Verbosity;
+use rumorph::marker::MarkerTraits;
 use rumorph::report::{default_report_logger, init_report_logger, ReportLevel};
 use rumorph::{RuMorphConfig, compile_time_sysroot, progress_info, analyze, RUMORPH_DEFAULT_ARGS};
 
@@ -49,7 +53,7 @@ impl rustc_driver::Callbacks for RuMorphCompilerCalls {
                 "Input file name: {}",
                 tcx.sess.io.input.filestem().to_string()
             );
-            analyze(tcx, self.config);
+            analyze(tcx, self.config.clone());
         });
         progress_info!("RuMorph finished");
 
@@ -76,6 +80,8 @@ fn parse_config() -> (RuMorphConfig, Vec<String>) {
             "-Zrumorph-disable-errhandle" => config.errhandle_enabled = false,
             "-Zrumorph-enable-truncation" => config.truncation_enabled = true,
             "-Zrumorph-disable-truncation" => config.truncation_enabled = false,
+            "-Zrumorph-enable-mono-layout" => config.mono_layout_enabled = true,
+            "-Zrumorph-disable-mono-layout" => config.mono_layout_enabled = false,
             "-v" => config.verbosity = Verbosity::Verbose,
             "-vv" => config.verbosity = Verbosity::Trace,
             "-Zsensitivity-high" => config.report_level = ReportLevel::Error,
@@ -83,12 +89,33 @@ fn parse_config() -> (RuMorphConfig, Vec<String>) {
             "-Zsensitivity-low" => config.report_level = ReportLevel::Info,
             "-Zenable-optimize" => config.optimize_enabled = true,
             "-Zdisable-optimize" => config.optimize_enabled = false,
+            "-Zrumorph-flow-sensitive" => config.flow_sensitive_enabled = true,
+            "-Zrumorph-enable-interprocedural" => config.interprocedural_enabled = true,
+            "-Zrumorph-disable-interprocedural" => config.interprocedural_enabled = false,
             _ => {
-                rustc_args.push(arg);
+                if let Some(pattern) = arg.strip_prefix("-Zrumorph-dump-graph=") {
+                    config.dump_graph_pattern = Some(pattern.to_owned());
+                } else if let Some(triples) = arg.strip_prefix("-Zrumorph-layout-targets=") {
+                    config.layout_targets = triples
+                        .split(',')
+                        .filter(|triple| !triple.is_empty())
+                        .map(TargetTriple::from_triple)
+                        .collect();
+                } else if let Some(specs) = arg.strip_prefix("-Zrumorph-marker-traits=") {
+                    // e.g. `my::Plain:any-bit-pattern+no-padding,my::Packed:unaligned`
+                    let specs: Vec<&str> = specs.split(',').filter(|spec| !spec.is_empty()).collect();
+                    config.marker_traits = MarkerTraits::new(&specs);
+                } else {
+                    rustc_args.push(arg);
+                }
             }
         }
     }
 
+    if let Some(dir) = env::var_os("RUMORPH_GRAPH_DIR") {
+        config.graph_dir = PathBuf::from(dir);
+    }
+
     (config, rustc_args)
 }
 
@@ -128,6 +155,12 @@ fn run_compiler(
 }
 
 fn main() {
+    // `rumorph diff <old> <new>` compares two reports and doesn't invoke the compiler
+    if let Some("diff") = env::args().nth(1).as_deref() {
+        let args: Vec<String> = env::args().skip(2).collect();
+        std::process::exit(rumorph::diff::run(&args));
+    }
+
     rustc_driver::install_ice_hook(
         "https://github.com/shinmao/RuMorph/issues/new",
         |_| ()
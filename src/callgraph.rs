@@ -0,0 +1,235 @@
+//! Crate call graph over the collected bodies.
+//! Edges come from the same call resolution as the IR translation (`crate::resolve`), read from
+//! MIR directly so the graph can be built before any body is translated.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir;
+use rustc_middle::ty::TyCtxt;
+
+use crate::graph::{Graph, Scc};
+use crate::resolve::{CallResolver, ResolvedCall};
+
+pub struct CallGraph {
+    nodes: Vec<DefId>,
+    ids: HashMap<DefId, usize>,
+    callees: Vec<Vec<usize>>,
+    callers: Vec<Vec<usize>>,
+    /// Local items reachable from outside the crate
+    public: Vec<bool>,
+}
+
+impl Graph for CallGraph {
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn next(&self, id: usize) -> &[usize] {
+        &self.callees[id]
+    }
+
+    fn prev(&self, id: usize) -> &[usize] {
+        &self.callers[id]
+    }
+}
+
+impl CallGraph {
+    /// Starts from `roots` and follows every local callee for which `body_of` returns MIR.
+    /// Callees without a body (e.g. external functions) become leaf nodes.
+    pub fn build<'tcx>(
+        tcx: TyCtxt<'tcx>,
+        roots: impl IntoIterator<Item = DefId>,
+        body_of: impl Fn(DefId) -> Option<&'tcx mir::Body<'tcx>>,
+    ) -> Self {
+        let mut graph = CallGraph {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            callees: Vec::new(),
+            callers: Vec::new(),
+            public: Vec::new(),
+        };
+
+        let mut expanded = HashSet::new();
+        let mut work_list: VecDeque<DefId> = roots.into_iter().collect();
+        while let Some(caller) = work_list.pop_front() {
+            let caller_id = graph.insert(caller);
+            if !expanded.insert(caller) {
+                continue;
+            }
+            let body = match body_of(caller) {
+                Some(body) => body,
+                None => continue,
+            };
+            for callee in call_targets(tcx, body) {
+                let callee_id = graph.insert(callee);
+                if !graph.callees[caller_id].contains(&callee_id) {
+                    graph.callees[caller_id].push(callee_id);
+                    graph.callers[callee_id].push(caller_id);
+                }
+                if callee.is_local() {
+                    work_list.push_back(callee);
+                }
+            }
+        }
+
+        let effective_visibilities = tcx.effective_visibilities(());
+        graph.public = graph
+            .nodes
+            .iter()
+            .map(|def_id| {
+                def_id
+                    .as_local()
+                    .map_or(false, |local| effective_visibilities.is_reachable(local))
+            })
+            .collect();
+
+        graph
+    }
+
+    fn insert(&mut self, def_id: DefId) -> usize {
+        if let Some(&id) = self.ids.get(&def_id) {
+            return id;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(def_id);
+        self.ids.insert(def_id, id);
+        self.callees.push(Vec::new());
+        self.callers.push(Vec::new());
+        id
+    }
+
+    pub fn def_id(&self, id: usize) -> DefId {
+        self.nodes[id]
+    }
+
+    pub fn node(&self, def_id: DefId) -> Option<usize> {
+        self.ids.get(&def_id).copied()
+    }
+
+    pub fn callees_of(&self, def_id: DefId) -> Vec<DefId> {
+        self.neighbors(def_id, &self.callees)
+    }
+
+    pub fn callers_of(&self, def_id: DefId) -> Vec<DefId> {
+        self.neighbors(def_id, &self.callers)
+    }
+
+    fn neighbors(&self, def_id: DefId, edges: &[Vec<usize>]) -> Vec<DefId> {
+        self.node(def_id)
+            .map(|id| edges[id].iter().map(|&next| self.nodes[next]).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn is_public(&self, def_id: DefId) -> bool {
+        self.node(def_id).map_or(false, |id| self.public[id])
+    }
+
+    /// Functions transitively callable from the public API of the crate
+    pub fn reachable_from_public_api(&self) -> HashSet<DefId> {
+        let roots: Vec<usize> = (0..self.len()).filter(|&id| self.public[id]).collect();
+        self.bfs(roots, &self.callees)
+            .into_iter()
+            .map(|id| self.nodes[id])
+            .collect()
+    }
+
+    /// Public functions from which `def_id` can be called, itself included, closest first
+    pub fn public_entries_reaching(&self, def_id: DefId) -> Vec<DefId> {
+        let start = match self.node(def_id) {
+            Some(id) => id,
+            None => return Vec::new(),
+        };
+        self.bfs(vec![start], &self.callers)
+            .into_iter()
+            .filter(|&id| self.public[id])
+            .map(|id| self.nodes[id])
+            .collect()
+    }
+
+    /// Groups of mutually recursive functions, including functions calling themselves
+    pub fn recursive_cycles(&self) -> Vec<Vec<DefId>> {
+        let scc = Scc::construct(self);
+        (0..scc.num_groups())
+            .map(|group| scc.nodes_in_group(group))
+            .filter(|nodes| nodes.len() > 1 || self.callees[nodes[0]].contains(&nodes[0]))
+            .map(|nodes| nodes.iter().map(|&id| self.nodes[id]).collect())
+            .collect()
+    }
+
+    /// Callees before callers; the members of a recursive cycle are adjacent
+    pub fn bottom_up_order(&self) -> Vec<DefId> {
+        let scc = Scc::construct(self);
+        scc.topological_order()
+            .into_iter()
+            .rev()
+            .flat_map(|group| scc.nodes_in_group(group).iter().map(|&id| self.nodes[id]))
+            .collect()
+    }
+
+    fn bfs(&self, roots: Vec<usize>, edges: &[Vec<usize>]) -> Vec<usize> {
+        let mut visited = vec![false; self.len()];
+        let mut work_list: VecDeque<usize> = roots.into_iter().collect();
+        for &root in &work_list {
+            visited[root] = true;
+        }
+
+        let mut order = Vec::new();
+        while let Some(current) = work_list.pop_front() {
+            order.push(current);
+            for &next in &edges[current] {
+                if !visited[next] {
+                    visited[next] = true;
+                    work_list.push_back(next);
+                }
+            }
+        }
+        order
+    }
+
+    /// Graphviz rendering, one node per function
+    pub fn to_dot(&self, tcx: TyCtxt<'_>) -> String {
+        let mut dot = String::from("digraph callgraph {\n");
+        for (id, &def_id) in self.nodes.iter().enumerate() {
+            let shape = if self.public[id] { "box" } else { "ellipse" };
+            dot.push_str(&format!(
+                "    n{} [label=\"{}\", shape={}];\n",
+                id,
+                tcx.def_path_str(def_id).replace('"', "\\\""),
+                shape
+            ));
+        }
+        for (from, tos) in self.callees.iter().enumerate() {
+            for to in tos {
+                dot.push_str(&format!("    n{} -> n{};\n", from, to));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Call targets of `body`: the (resolved) static callee, or every candidate of an indirect call
+pub fn call_targets<'tcx>(tcx: TyCtxt<'tcx>, body: &mir::Body<'tcx>) -> Vec<DefId> {
+    let resolver = CallResolver::new(tcx, body);
+    let mut targets = Vec::new();
+    for bb in body.basic_blocks.iter() {
+        if let Some(mir::Terminator {
+            kind: mir::TerminatorKind::Call { func, .. },
+            ..
+        }) = &bb.terminator
+        {
+            match resolver.resolve(func) {
+                ResolvedCall::Static { callee, resolved } => {
+                    targets.push(resolved.unwrap_or(callee).did)
+                },
+                ResolvedCall::Indirect { callees, .. } => {
+                    targets.extend(callees.iter().map(|callee| callee.did))
+                },
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    targets.retain(|did| seen.insert(*did));
+    targets
+}
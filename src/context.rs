@@ -1,20 +1,33 @@
+use std::cell::OnceCell;
+use std::fmt;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use rustc_hir::{
     def_id::{DefId, LocalDefId},
     BodyId, ConstContext, HirId,
 };
-use rustc_middle::mir::{self, TerminatorKind, StatementKind, Rvalue, Operand};
-use rustc_middle::ty::{Ty, TyCtxt, TyKind};
-use rustc_span::Span;
+use rustc_middle::mir::{self, TerminatorKind, StatementKind};
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::{sym, Span};
+use rustc_target::spec::TargetTriple;
 use crate::progress_info;
 
 use dashmap::DashMap;
+use parking_lot::Mutex;
 use snafu::Snafu;
 
+use crate::dump;
+use crate::graph::{GraphTaint, TaintEngine};
 use crate::ir;
 use crate::prelude::*;
-use crate::report::ReportLevel;
+use crate::callgraph::CallGraph;
+use crate::resolve::{CallResolver, ResolvedCall};
+use crate::summary::FnSummary;
+use crate::target::TargetLayout;
+use crate::marker::{MarkerTraits, TraitImpls};
+use crate::report::{global_report_logger, Findings, Report, ReportLevel, ReportLogger};
 use crate::visitor::{create_adt_impl_map, AdtImplMap, RelatedFnCollector, RelatedItemMap};
 
 #[derive(Debug, Snafu, Clone)]
@@ -31,17 +44,33 @@ impl AnalysisError for MirInstantiationError {
     }
 }
 
-pub type RuMorphCtxt<'tcx> = &'tcx RuMorphCtxtOwner<'tcx>;
+pub type RuMorphCtxt<'a, 'tcx> = &'a RuMorphCtxtOwner<'tcx>;
 pub type TranslationResult<'tcx, T> = Result<T, MirInstantiationError>;
 
 /// Maps Instance to MIR and cache the result.
 pub struct RuMorphCtxtOwner<'tcx> {
     tcx: TyCtxt<'tcx>,
     translation_cache: DashMap<DefId, Rc<TranslationResult<'tcx, ir::Body<'tcx>>>>,
+    summary_cache: DashMap<DefId, Rc<FnSummary>>,
+    trait_impl_cache: DashMap<DefId, Rc<TraitImpls<'tcx>>>,
+    call_graph: OnceCell<CallGraph>,
     related_item_cache: RelatedItemMap,
     adt_impl_cache: AdtImplMap<'tcx>,
     report_level: ReportLevel,
     optimize_option: bool,
+    flow_sensitive: bool,
+    /// `-Zrumorph-dump-graph` pattern matched against function paths
+    dump_graph_pattern: Option<String>,
+    /// Directory of the dumped graphs
+    graph_dir: PathBuf,
+    /// Targets whose layouts `LayoutChecker` compares besides the host
+    layout_targets: Vec<TargetLayout>,
+    /// Guarantees of marker traits used as bounds, consulted by the layout checkers
+    marker_traits: MarkerTraits,
+    /// Injected report sink; the global report logger is used when this is `None`
+    report_sink: Option<Arc<dyn ReportLogger>>,
+    /// Every report emitted in this context, returned by `take_findings`
+    findings: Mutex<Vec<Report>>,
 }
 
 /// Visit MIR body and returns a RuMorph IR function
@@ -49,13 +78,32 @@ pub struct RuMorphCtxtOwner<'tcx> {
 /// https://doc.rust-lang.org/nightly/nightly-rustc/rustc/mir/visit/trait.Visitor.html
 impl<'tcx> RuMorphCtxtOwner<'tcx> {
     pub fn new(tcx: TyCtxt<'tcx>, report_level: ReportLevel, optimize_option: bool) -> Self {
+        Self::with_sink(tcx, report_level, optimize_option, None)
+    }
+
+    pub fn with_sink(
+        tcx: TyCtxt<'tcx>,
+        report_level: ReportLevel,
+        optimize_option: bool,
+        report_sink: Option<Arc<dyn ReportLogger>>,
+    ) -> Self {
         RuMorphCtxtOwner {
             tcx,
             translation_cache: DashMap::new(),
+            summary_cache: DashMap::new(),
+            trait_impl_cache: DashMap::new(),
+            call_graph: OnceCell::new(),
             related_item_cache: RelatedFnCollector::collect(tcx),
             adt_impl_cache: create_adt_impl_map(tcx),
             report_level,
             optimize_option,
+            flow_sensitive: false,
+            dump_graph_pattern: None,
+            graph_dir: PathBuf::from(dump::DEFAULT_GRAPH_DIR),
+            layout_targets: Vec::new(),
+            marker_traits: MarkerTraits::default(),
+            report_sink,
+            findings: Mutex::new(Vec::new()),
         }
     }
 
@@ -67,6 +115,75 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
         self.optimize_option
     }
 
+    /// Whether checkers should use `crate::dataflow::FlowTaintAnalyzer`
+    pub fn flow_sensitive(&self) -> bool {
+        self.flow_sensitive
+    }
+
+    pub fn set_flow_sensitive(&mut self, flow_sensitive: bool) {
+        self.flow_sensitive = flow_sensitive;
+    }
+
+    pub fn set_dump_graph_pattern(&mut self, pattern: Option<String>) {
+        self.dump_graph_pattern = pattern;
+    }
+
+    /// Directory the graphs of `set_dump_graph_pattern` are written to
+    pub fn set_graph_dir(&mut self, dir: PathBuf) {
+        self.graph_dir = dir;
+    }
+
+    /// Loads the data layouts of `triples`; unknown targets are skipped with a warning
+    pub fn set_layout_targets(&mut self, triples: &[TargetTriple]) {
+        let sess = self.tcx.sess;
+        self.layout_targets = triples
+            .iter()
+            .filter_map(|triple| TargetLayout::load(sess, triple))
+            .collect();
+    }
+
+    pub fn layout_targets(&self) -> &[TargetLayout] {
+        &self.layout_targets
+    }
+
+    pub fn set_marker_traits(&mut self, marker_traits: MarkerTraits) {
+        self.marker_traits = marker_traits;
+    }
+
+    pub fn marker_traits(&self) -> &MarkerTraits {
+        &self.marker_traits
+    }
+
+    /// Impls of the trait `trait_def_id`; upstream impls are only walked for marker traits with
+    /// guarantees, other traits only see the impls of this crate
+    pub fn trait_impls(&self, trait_def_id: DefId) -> Rc<TraitImpls<'tcx>> {
+        let tcx = self.tcx();
+        let result = self.trait_impl_cache.entry(trait_def_id).or_insert_with(|| {
+            let with_upstream = self
+                .marker_traits
+                .guarantee(&tcx.def_path_str(trait_def_id))
+                .map_or(false, |guarantee| !guarantee.is_empty());
+            Rc::new(TraitImpls::collect(tcx, trait_def_id, with_upstream))
+        });
+
+        result.clone()
+    }
+
+    /// Writes the DOT graphs of `body` as seen by `checker` if its path matches the dump pattern
+    pub fn dump_graphs<T: GraphTaint + fmt::Debug>(
+        &self,
+        checker: &str,
+        body: &ir::Body<'tcx>,
+        engine: &dyn TaintEngine<T>,
+    ) {
+        if let Some(pattern) = &self.dump_graph_pattern {
+            let fn_path = self.tcx.def_path_str(body.original.source.def_id());
+            if fn_path.contains(pattern.as_str()) {
+                dump::write_graphs(&self.graph_dir, &fn_path, checker, body, engine);
+            }
+        }
+    }
+
     pub fn types_with_related_items(
         &self,
     ) -> impl Iterator<Item = (Option<HirId>, (BodyId, Span))> + '_ {
@@ -77,11 +194,6 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
 
     pub fn translate_body(&self, def_id: DefId) -> Rc<TranslationResult<'tcx, ir::Body<'tcx>>> {
         let tcx = self.tcx();
-        //let codegen_list = tcx.collect_and_partition_mono_items(()).1;
-        //for cg in codegen_list {
-        //    progress_info!("The name of codegen: {:?}", cg.name());
-        //    progress_info!("The items: {:?}", cg.items());
-        //}
         let result = self.translation_cache.entry(def_id).or_insert_with(|| {
             Rc::new(
                 try {
@@ -105,94 +217,49 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
             .collect::<Vec<_>>();
 
         progress_info!("size: {:?}", body.basic_blocks.len());
+        let resolver = CallResolver::new(self.tcx, body);
         let mut bb_successor_list: Vec<Vec<usize>> = vec![vec![]; body.basic_blocks.len()];
         let basic_blocks: Vec<_> = body
             .basic_blocks
             .iter()
             .enumerate()
-            .map(|(idx, basic_block)| self.translate_basic_block(&mut bb_successor_list[idx], basic_block))
+            .map(|(idx, basic_block)| {
+                self.translate_basic_block(body, &resolver, &mut bb_successor_list[idx], basic_block)
+            })
             .collect::<Result<Vec<_>, _>>()?;
 
         progress_info!("bb_successor_list: {:?}", bb_successor_list);
         
-        // we only locate local rather than place
-        // e.g., (*_3).field: we would only locate _3
-        let mut v = Vec::new();
-        for _ in 0..local_decls.len() {
-            let mut vv = Vec::new();
-            v.push(vv);
-        }
-
+        // collect (read place, written place) pairs first, so that every place
+        // is interned before the edges from its prefixes and projections are added
+        let mut flows: Vec<(mir::Place<'tcx>, mir::Place<'tcx>)> = Vec::new();
         for bb in &basic_blocks {
             for statement in &bb.statements {
                 // statement: mir::Statement
-                match &statement.kind {
-                    StatementKind::Assign(box (lplace, rval)) => {
-                        match rval {
-                            Rvalue::Cast(_, op, _)
-                            | Rvalue::Use(op)
-                            | Rvalue::Repeat(op, _)
-                            | Rvalue::ShallowInitBox(op, _) => {
-                                match op {
-                                    Operand::Copy(rplace) | Operand::Move(rplace) => {
-                                        let id = rplace.local.index();
-                                        v[id].push(lplace.local.index());
-                                    },
-                                    _ => {},
-                                }
-                            },
-                            Rvalue::Ref(_, _, rplace)
-                            | Rvalue::AddressOf(_, rplace)
-                            | Rvalue::Len(rplace)
-                            | Rvalue::Discriminant(rplace)
-                            | Rvalue::CopyForDeref(rplace) => {
-                                let id = rplace.local.index();
-                                v[id].push(lplace.local.index());
-                            },
-                            Rvalue::BinaryOp(_, box (op1, op2))
-                            | Rvalue::CheckedBinaryOp(_, box (op1, op2)) => {
-                                let id1 = op1.place();
-                                let id2 = op2.place();
-                                match id1 {
-                                    Some(idx) => {
-                                        let idx1 = idx.local.index();
-                                        v[idx1].push(lplace.local.index());
-                                    },
-                                    None => {},
-                                }
-                                match id2 {
-                                    Some(idx) => {
-                                        let idx2 = idx.local.index();
-                                        v[idx2].push(lplace.local.index());
-                                    },
-                                    None => {},
-                                }
-                            },
-                            _ => {},
-                        }
-                    },
-                    // StatementKind::SetDiscriminant {
-                    _ => {},
+                if let StatementKind::Assign(box (lplace, rval)) = &statement.kind {
+                    for rplace in ir::rvalue_reads(rval) {
+                        flows.push((rplace, *lplace));
+                    }
                 }
             }
 
             // we also need to handle terminator case
+            // a summarized local callee tells which arguments actually reach `dest`
+            let summary = bb.terminator.static_callee().and_then(|callee| self.summary(callee));
+            if let (ir::TerminatorKind::StaticCall { args, dest, .. }, Some(summary)) =
+                (&bb.terminator.kind, &summary)
+            {
+                flows.extend(summary.call_flows(self.tcx, args, *dest));
+                continue;
+            }
             match &bb.terminator.kind {
                 // ir::Terminator
-                ir::TerminatorKind::StaticCall {
-                    callee_did,
-                    callee_substs,
-                    ref args,
-                    dest,
-                } => {
+                ir::TerminatorKind::StaticCall { ref args, dest, .. }
+                | ir::TerminatorKind::IndirectCall { ref args, dest, .. } => {
                     for arg in args {
                         // arg: mir::Operand
-                        match arg {
-                            Operand::Copy(pl) | Operand::Move(pl) => {
-                                let id = pl.local.index();
-                                v[id].push(dest.local.index());
-                            },
-                            _ => {},
+                        if let Some(pl) = arg.place() {
+                            flows.push((pl, *dest));
                         }
                     }
                 },
@@ -200,35 +267,115 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
             }
         }
 
+        let mut place_index = ir::PlaceIndex::new(local_decls.len());
+        for (from, to) in &flows {
+            place_index.intern(from);
+            place_index.intern(to);
+        }
+        // written places without a read, e.g. `_5.0 = const 0`, need their own node too,
+        // or a write would be attributed to their prefix
+        for bb in &basic_blocks {
+            for statement in &bb.statements {
+                if let StatementKind::Assign(box (lplace, _)) = &statement.kind {
+                    place_index.intern(lplace);
+                }
+            }
+            match &bb.terminator.kind {
+                ir::TerminatorKind::StaticCall { dest, .. } | ir::TerminatorKind::IndirectCall { dest, .. } => {
+                    place_index.intern(dest);
+                },
+                _ => {},
+            }
+        }
+
+        // a read of `(*_3).0` observes `_3`, `*_3` and `(*_3).0.1`, but not `(*_3).1`
+        let mut v = vec![Vec::new(); place_index.len()];
+        for (from, to) in &flows {
+            let to_id = place_index.get(to);
+            for from_id in place_index.read_nodes(from) {
+                if !v[from_id].contains(&to_id) {
+                    v[from_id].push(to_id);
+                }
+            }
+        }
+
+        let source_map = self.tcx.sess.source_map();
+        let source_lines = basic_blocks
+            .iter()
+            .map(|bb| {
+                bb.statements
+                    .iter()
+                    .map(|statement| statement.source_info.span)
+                    .chain(std::iter::once(bb.terminator.original.source_info.span))
+                    .map(|span| source_map.lookup_char_pos(span.lo()).line)
+                    .collect()
+            })
+            .collect();
+
+        let mut predecessors = vec![Vec::new(); place_index.len()];
+        for (from_id, tos) in v.iter().enumerate() {
+            for &to_id in tos {
+                predecessors[to_id].push(from_id);
+            }
+        }
+
         Ok(ir::Body {
             local_decls,
             original_decls: body.local_decls.to_owned(),
             basic_blocks,
             original: body.to_owned(),
+            place_index,
             place_neighbor_list: v,
+            place_predecessor_list: predecessors,
             bb_neighbor_list: bb_successor_list, 
+            source_lines,
+            dominators: OnceCell::new(),
+            post_dominators: OnceCell::new(),
+            may_panic: OnceCell::new(),
         })
     }
 
     fn translate_basic_block(
         &self,
+        body: &mir::Body<'tcx>,
+        resolver: &CallResolver<'_, 'tcx>,
         bb_neighbors:&mut Vec<usize>,
         basic_block: &mir::BasicBlockData<'tcx>,
     ) -> TranslationResult<'tcx, ir::BasicBlock<'tcx>> {
-        let statements = basic_block
+        let mut statements = basic_block
             .statements
             .iter()
             .map(|statement| statement.clone())
             .collect::<Vec<_>>();
 
-        let terminator = self.translate_terminator(
+        let mut terminator = self.translate_terminator(
             basic_block
                 .terminator
                 .as_ref()
                 .expect("Terminator should not be empty at this point"),
+            resolver,
             bb_neighbors
         )?;
 
+        // Const-eval MIR still calls the `transmute` intrinsic; lower it to the
+        // `CastKind::Transmute` that optimized MIR uses, so the checkers see one form
+        if let ir::TerminatorKind::StaticCall { callee_did, args, dest, .. } = &terminator.kind
+            && let TerminatorKind::Call { target: Some(target), .. } = &terminator.original.kind
+            && args.len() == 1
+            && self.tcx.is_intrinsic(*callee_did)
+            && self.tcx.item_name(*callee_did) == sym::transmute
+        {
+            let dest_ty = dest.ty(body, self.tcx).ty;
+            statements.push(mir::Statement {
+                source_info: terminator.original.source_info,
+                kind: StatementKind::Assign(Box::new((
+                    *dest,
+                    mir::Rvalue::Cast(mir::CastKind::Transmute, args[0].clone(), dest_ty),
+                ))),
+            });
+            terminator.kind = ir::TerminatorKind::Goto(target.index());
+        }
+
         Ok(ir::BasicBlock {
             statements,
             terminator,
@@ -239,6 +386,7 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
     fn translate_terminator(
         &self,
         terminator: &mir::Terminator<'tcx>,
+        resolver: &CallResolver<'_, 'tcx>,
         successor_list: &mut Vec<usize>
     ) -> TranslationResult<'tcx, ir::Terminator<'tcx>> {
         progress_info!("original terminator: {:?}", terminator);
@@ -254,30 +402,28 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
                     args,
                     destination: dest,
                     target,
+                    unwind,
                     ..
                 } => {
+                    if let Some(bb) = target {
+                        successor_list.push(bb.index());
+                    }
+                    push_unwind(successor_list, unwind);
 
-                    if let mir::Operand::Constant(box func) = func_operand {
-                        let func_ty = func.literal.ty();
-                        match func_ty.kind() {
-                            TyKind::FnDef(def_id, callee_substs) => {
-                                if let Some(bb) = target {
-                                    successor_list.push(bb.index());
-                                }
-                                ir::TerminatorKind::StaticCall {
-                                    callee_did: *def_id,
-                                    callee_substs,
-                                    args: args.clone(),
-                                    dest: *dest,
-                                }
-                            },
-                            TyKind::FnPtr(_) => ir::TerminatorKind::FnPtr {
-                                value: func.literal.clone(),
-                            },
-                            _ => panic!("invalid callee of type {:?}", func_ty),
-                        }
-                    } else {
-                        ir::TerminatorKind::Unimplemented("non-constant function call".into())
+                    match resolver.resolve(func_operand) {
+                        ResolvedCall::Static { callee, resolved } => ir::TerminatorKind::StaticCall {
+                            callee_did: callee.did,
+                            callee_substs: callee.substs,
+                            args: args.clone(),
+                            dest: *dest,
+                            resolved,
+                        },
+                        ResolvedCall::Indirect { kind, callees } => ir::TerminatorKind::IndirectCall {
+                            kind,
+                            callees,
+                            args: args.clone(),
+                            dest: *dest,
+                        },
                     }
                 },
                 TerminatorKind::SwitchInt { discr, targets } => {
@@ -289,61 +435,146 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
                         targets: targets.clone() 
                     }
                 },
-                TerminatorKind::Drop { place, target, .. } => {
-                    // TODO: implement Drop and DropAndReplace terminators
+                TerminatorKind::Drop { place, target, unwind, .. } => {
                     successor_list.push(target.index());
-                    ir::TerminatorKind::Unimplemented(
-                        format!("TODO terminator: {:?}", terminator).into(),
-                    )
+                    push_unwind(successor_list, unwind);
+                    ir::TerminatorKind::Drop {
+                        place: *place,
+                        target: target.index(),
+                        unwind: unwind_target(unwind),
+                    }
                 },
-                TerminatorKind::Assert { cond, expected, msg, target, .. } => {
+                TerminatorKind::Assert { cond, expected, msg, target, unwind } => {
                     successor_list.push(target.index());
-                    ir::TerminatorKind::Unimplemented(
-                        format!("TODO terminator: {:?}", terminator).into(),
-                    )
+                    push_unwind(successor_list, unwind);
+                    ir::TerminatorKind::Assert {
+                        cond: cond.clone(),
+                        expected: *expected,
+                        msg: (**msg).clone(),
+                        target: target.index(),
+                        unwind: unwind_target(unwind),
+                    }
                 },
-                TerminatorKind::Yield { value, resume, .. } => {
+                TerminatorKind::Yield { value, resume, resume_arg, drop } => {
                     successor_list.push(resume.index());
-                    ir::TerminatorKind::Unimplemented(
-                        format!("TODO terminator: {:?}", terminator).into(),
-                    )
+                    if let Some(bb) = drop {
+                        successor_list.push(bb.index());
+                    }
+                    ir::TerminatorKind::Yield {
+                        value: value.clone(),
+                        resume: resume.index(),
+                        resume_arg: *resume_arg,
+                        drop: drop.map(|bb| bb.index()),
+                    }
+                },
+                TerminatorKind::GeneratorDrop => ir::TerminatorKind::GeneratorDrop,
+                TerminatorKind::Resume => ir::TerminatorKind::UnwindResume,
+                TerminatorKind::Terminate => ir::TerminatorKind::UnwindTerminate,
+                TerminatorKind::Unreachable => ir::TerminatorKind::Unreachable,
+                TerminatorKind::InlineAsm { destination, unwind, .. } => {
+                    if let Some(bb) = destination {
+                        successor_list.push(bb.index());
+                    }
+                    push_unwind(successor_list, unwind);
+                    ir::TerminatorKind::InlineAsm {
+                        destination: destination.map(|bb| bb.index()),
+                        unwind: unwind_target(unwind),
+                    }
                 },
                 TerminatorKind::FalseEdge { real_target, .. } => {
+                    // The imaginary target is never taken at runtime
                     successor_list.push(real_target.index());
-                    ir::TerminatorKind::Unimplemented(
-                        format!("TODO terminator: {:?}", terminator).into(),
-                    )
+                    ir::TerminatorKind::Goto(real_target.index())
+                },
+                TerminatorKind::FalseUnwind { real_target, unwind } => {
+                    successor_list.push(real_target.index());
+                    push_unwind(successor_list, unwind);
+                    ir::TerminatorKind::FalseUnwind {
+                        real_target: real_target.index(),
+                        unwind: unwind_target(unwind),
+                    }
                 },
-                _ => ir::TerminatorKind::Unimplemented(
-                    format!("Unknown terminator: {:?}", terminator).into(),
-                ),
             },
             original: terminator.clone(),
         })
     }
 
+    /// Summary of a local function, if `compute_summaries` has reached it
+    pub fn summary(&self, def_id: DefId) -> Option<Rc<FnSummary>> {
+        self.summary_cache.get(&def_id).map(|summary| summary.clone())
+    }
+
+    /// Call graph of the collected bodies and the local functions they reach
+    pub fn call_graph(&self) -> &CallGraph {
+        self.call_graph.get_or_init(|| {
+            let tcx = self.tcx();
+            let roots: Vec<DefId> = self
+                .types_with_related_items()
+                .map(|(_, (body_id, _))| tcx.hir().body_owner_def_id(body_id).to_def_id())
+                .collect();
+            CallGraph::build(tcx, roots, |def_id| {
+                if def_id.is_local() {
+                    Self::find_fn(tcx, def_id).ok()
+                } else {
+                    None
+                }
+            })
+        })
+    }
+
+    /// Public functions of the crate that can reach `def_id`, for report messages,
+    /// e.g. "reachable from `decode`"
+    pub fn public_entry_description(&self, def_id: DefId) -> Option<String> {
+        let tcx = self.tcx();
+        let entries = self.call_graph().public_entries_reaching(def_id);
+        let first = entries.first()?;
+        let more = if entries.len() > 1 {
+            format!(" and {} more", entries.len() - 1)
+        } else {
+            String::new()
+        };
+        Some(format!("reachable from `{}`{}", tcx.def_path_str(*first), more))
+    }
+
+    /// Computes the summaries of the local functions in the call graph, callees first.
+    /// Bodies in a recursive cycle see the conservative call edges for the calls that close the cycle.
+    pub fn compute_summaries(&self) {
+        for def_id in self.call_graph().bottom_up_order() {
+            if !def_id.is_local() {
+                continue;
+            }
+            if let Ok(body) = self.translate_body(def_id).as_ref() {
+                let summary = FnSummary::compute(body, |callee| self.summary(callee));
+                self.summary_cache.insert(def_id, Rc::new(summary));
+            }
+        }
+    }
+
     fn translate_local_decl(&self, local_decl: &mir::LocalDecl<'tcx>) -> ir::LocalDecl<'tcx> {
         ir::LocalDecl { ty: local_decl.ty }
     }
 
     /// Try to find MIR function body with def_id.
+    /// Consts and statics (associated ones included) only have the MIR built for const evaluation,
+    /// which is also the fallback for const fns without optimized MIR.
     fn find_fn(
         tcx: TyCtxt<'tcx>,
         def_id: DefId,
     ) -> Result<&'tcx mir::Body<'tcx>, MirInstantiationError> {
-        if tcx.is_mir_available(def_id)
-            && matches!(
-                tcx.hir().body_const_context(def_id.expect_local()),
-                None | Some(ConstContext::ConstFn)
-            )
-        {
-            Ok(tcx.optimized_mir(def_id))
-        } else {
-            debug!(
-                "Skipping an item {:?}, no MIR available for this item",
-                def_id
-            );
-            NotAvailable { def_id }.fail()
+        let const_context = def_id
+            .as_local()
+            .and_then(|local_def_id| tcx.hir().body_const_context(local_def_id));
+        match const_context {
+            Some(ConstContext::Const | ConstContext::Static(_)) => Ok(tcx.mir_for_ctfe(def_id)),
+            _ if tcx.is_mir_available(def_id) => Ok(tcx.optimized_mir(def_id)),
+            _ if tcx.is_ctfe_mir_available(def_id) => Ok(tcx.mir_for_ctfe(def_id)),
+            _ => {
+                debug!(
+                    "Skipping an item {:?}, no MIR available for this item",
+                    def_id
+                );
+                NotAvailable { def_id }.fail()
+            }
         }
     }
 
@@ -354,4 +585,34 @@ impl<'tcx> RuMorphCtxtOwner<'tcx> {
     pub fn report_level(&self) -> ReportLevel {
         self.report_level
     }
+
+    /// Records a report and forwards it to the injected sink,
+    /// or to the global report logger if it was initialized.
+    pub fn report(&self, report: Report) {
+        if let Some(sink) = &self.report_sink {
+            sink.log(report.clone());
+        } else if let Some(logger) = global_report_logger() {
+            logger.log(report.clone());
+        }
+        self.findings.lock().push(report);
+    }
+
+    /// Returns the reports emitted so far and resets the list.
+    pub fn take_findings(&self) -> Findings {
+        Findings::new(std::mem::take(&mut *self.findings.lock()))
+    }
+}
+
+/// Returns the cleanup block of an unwind action, if any.
+fn unwind_target(unwind: &mir::UnwindAction) -> Option<usize> {
+    match unwind {
+        mir::UnwindAction::Cleanup(bb) => Some(bb.index()),
+        _ => None,
+    }
+}
+
+fn push_unwind(successor_list: &mut Vec<usize>, unwind: &mir::UnwindAction) {
+    if let Some(bb) = unwind_target(unwind) {
+        successor_list.push(bb);
+    }
 }
@@ -0,0 +1,302 @@
+//! Flow-sensitive taint propagation over the CFG of a translated body.
+//! Unlike `TaintAnalyzer::propagate`, which walks the flow-insensitive `place_neighbor_list`,
+//! this keeps one taint state per place node and program point, so an assignment kills
+//! the taint previously held by its destination and flows follow program order.
+
+use std::collections::VecDeque;
+
+use rustc_middle::mir::{self, StatementKind};
+
+use crate::context::RuMorphCtxt;
+use crate::graph::{GraphTaint, TaintAnalyzer, TaintEngine};
+use crate::ir;
+
+/// Returns the flow-sensitive analyzer if it is enabled in `rcx`, the graph-based one otherwise.
+pub fn body_taint_analyzer<'a, 'tcx, T: GraphTaint + 'a>(
+    rcx: RuMorphCtxt<'a, 'tcx>,
+    body: &'a ir::Body<'tcx>,
+) -> Box<dyn TaintEngine<T> + 'a> {
+    if rcx.flow_sensitive() {
+        Box::new(FlowTaintAnalyzer::new(rcx, body))
+    } else {
+        Box::new(TaintAnalyzer::new(body))
+    }
+}
+
+/// Sources are generated at the body entry and at every definition of the source node.
+/// A sink is reported when it holds taint at a statement or terminator that reads or writes it.
+/// Calls to summarized local functions follow the summary's flows, like the place graph does.
+pub struct FlowTaintAnalyzer<'a, 'tcx, T: GraphTaint> {
+    rcx: RuMorphCtxt<'a, 'tcx>,
+    body: &'a ir::Body<'tcx>,
+    /// keeps the sources and sinks; also answers the flow-insensitive `is_reachable` queries
+    base: TaintAnalyzer<'a, ir::Body<'tcx>, T>,
+    /// join of the taint each node held at any block boundary
+    node_taint: Vec<T>,
+}
+
+impl<'a, 'tcx, T: GraphTaint> FlowTaintAnalyzer<'a, 'tcx, T> {
+    pub fn new(rcx: RuMorphCtxt<'a, 'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        FlowTaintAnalyzer {
+            rcx,
+            body,
+            base: TaintAnalyzer::new(body),
+            node_taint: vec![T::default(); body.place_index.len()],
+        }
+    }
+
+    fn read_taint(&self, state: &[T], places: &[mir::Place<'tcx>]) -> T {
+        let mut taint = T::default();
+        for place in places {
+            for id in self.body.read_nodes(place) {
+                taint.join(&state[id]);
+            }
+        }
+        taint
+    }
+
+    fn check_sinks(&self, state: &[T], ids: &[usize], hits: &mut [T]) {
+        let sinks = self.base.sinks();
+        for &id in ids {
+            if sinks[id] && !state[id].is_empty() {
+                hits[id].join(&state[id]);
+            }
+        }
+    }
+
+    fn check_read_sinks(&self, state: &[T], places: &[mir::Place<'tcx>], hits: &mut [T]) {
+        for place in places {
+            self.check_sinks(state, &self.body.read_nodes(place), hits);
+        }
+    }
+
+    /// Strong update for places without `Deref`/`Index` that have a node of their own,
+    /// weak update otherwise.
+    fn write(&self, state: &mut [T], place: &mir::Place<'tcx>, taint: T, hits: &mut [T]) {
+        let id = self.body.place_node(place);
+        let mut taint = self.base.sanitize(id, &taint);
+        taint.join(&self.base.sources()[id]);
+
+        let node = self.body.place_index.node(id);
+        // a node standing for a prefix of `place` also holds its siblings
+        let is_exact = !ir::PlaceNode::is_folded(place) && *node == ir::PlaceNode::from_place(place);
+        let is_strong = is_exact
+            && !node
+                .path
+                .iter()
+                .any(|step| matches!(step, ir::PlaceStep::Deref | ir::PlaceStep::Index));
+        if is_strong {
+            for projection in self.body.place_index.projections_of(id) {
+                state[projection] = T::default();
+            }
+            state[id] = taint;
+        } else {
+            state[id].join(&taint);
+        }
+
+        self.check_sinks(state, &[id], hits);
+    }
+
+    fn transfer_statement(&self, state: &mut [T], statement: &mir::Statement<'tcx>, hits: &mut [T]) {
+        match &statement.kind {
+            StatementKind::Assign(box (lplace, rval)) => {
+                let reads = ir::rvalue_reads(rval);
+                self.check_read_sinks(state, &reads, hits);
+                if lplace.is_indirect() {
+                    // writing through a pointer reads the pointer
+                    self.check_read_sinks(state, &[*lplace], hits);
+                }
+                let taint = self.read_taint(state, &reads);
+                self.write(state, lplace, taint, hits);
+            },
+            StatementKind::StorageDead(local) => {
+                for id in self.body.place_index.projections_of(local.index()) {
+                    state[id] = T::default();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn transfer_terminator(&self, state: &mut [T], terminator: &ir::Terminator<'tcx>, hits: &mut [T]) {
+        match &terminator.kind {
+            ir::TerminatorKind::StaticCall { args, dest, .. }
+            | ir::TerminatorKind::IndirectCall { args, dest, .. } => {
+                let reads: Vec<_> = args.iter().filter_map(|arg| arg.place()).collect();
+                self.check_read_sinks(state, &reads, hits);
+                match terminator.static_callee().and_then(|callee| self.rcx.summary(callee)) {
+                    Some(summary) => {
+                        // read every argument before the callee writes through any of them
+                        let mut dest_taint = T::default();
+                        let mut pointee_writes = Vec::new();
+                        for (from, to) in summary.call_flows(self.rcx.tcx(), args, *dest) {
+                            let taint = self.read_taint(state, &[from]);
+                            if to == *dest {
+                                dest_taint.join(&taint);
+                            } else {
+                                pointee_writes.push((to, taint));
+                            }
+                        }
+                        self.write(state, dest, dest_taint, hits);
+                        for (to, taint) in pointee_writes {
+                            self.write(state, &to, taint, hits);
+                        }
+                    },
+                    None => {
+                        let taint = self.read_taint(state, &reads);
+                        self.write(state, dest, taint, hits);
+                    },
+                }
+            },
+            ir::TerminatorKind::SwitchInt { discr, .. } => {
+                if let Some(place) = discr.place() {
+                    self.check_read_sinks(state, &[place], hits);
+                }
+            },
+            ir::TerminatorKind::Return => {
+                let ids = self.body.place_index.projections_of(mir::RETURN_PLACE.index());
+                self.check_sinks(state, &ids, hits);
+            },
+            _ => {},
+        }
+    }
+}
+
+impl<'a, 'tcx, T: GraphTaint> TaintEngine<T> for FlowTaintAnalyzer<'a, 'tcx, T> {
+    fn mark_source(&mut self, id: usize, taint: &T) {
+        self.base.mark_source(id, taint)
+    }
+
+    fn clear_source(&mut self, id: usize) {
+        self.base.clear_source(id)
+    }
+
+    fn is_reachable(&self, start_id: usize, end_id: usize) -> bool {
+        self.base.is_reachable(start_id, end_id)
+    }
+
+    fn is_reachable_from_any(&self, start_ids: &[usize], end_id: usize) -> bool {
+        self.base.is_reachable_from_any(start_ids, end_id)
+    }
+
+    // flow-insensitive, like `is_reachable`
+    fn sources_reaching(&self, sink_id: usize) -> Vec<usize> {
+        self.base.sources_reaching(sink_id)
+    }
+
+    fn mark_sink(&mut self, id: usize) {
+        self.base.mark_sink(id)
+    }
+
+    fn mark_sinks(&mut self, ids: Vec<usize>) {
+        self.base.mark_sinks(ids)
+    }
+
+    fn unmark_sink(&mut self, id: usize) {
+        self.base.unmark_sink(id)
+    }
+
+    fn mark_at_once(&mut self, id: usize, taint: &T) {
+        self.base.mark_at_once(id, taint)
+    }
+
+    fn mark_sanitizer(&mut self, id: usize) {
+        self.base.mark_sanitizer(id)
+    }
+
+    fn mark_sanitizer_mask(&mut self, id: usize, mask: &T) {
+        self.base.mark_sanitizer_mask(id, mask)
+    }
+
+    fn clear(&mut self) {
+        self.base.clear()
+    }
+
+    fn propagate(&mut self) -> T {
+        let len = self.base.sources().len();
+        let num_bb = self.body.basic_blocks.len();
+        let mut hits = vec![T::default(); len];
+        self.node_taint = vec![T::default(); len];
+        let mut entry_states: Vec<Option<Vec<T>>> = vec![None; num_bb];
+        let mut work_list = VecDeque::new();
+        let mut in_queue = vec![false; num_bb];
+
+        if num_bb > 0 {
+            let initial = self.base.sources().to_vec();
+            let all_nodes: Vec<usize> = (0..len).collect();
+            self.check_sinks(&initial, &all_nodes, &mut hits);
+            entry_states[0] = Some(initial);
+            work_list.push_back(0);
+            in_queue[0] = true;
+        }
+
+        while let Some(bb) = work_list.pop_front() {
+            in_queue[bb] = false;
+            let mut state = match &entry_states[bb] {
+                Some(state) => state.clone(),
+                None => continue,
+            };
+
+            let block = &self.body.basic_blocks[bb];
+            for statement in &block.statements {
+                self.transfer_statement(&mut state, statement, &mut hits);
+            }
+            self.transfer_terminator(&mut state, &block.terminator, &mut hits);
+            for (joined, taint) in self.node_taint.iter_mut().zip(state.iter()) {
+                joined.join(taint);
+            }
+
+            for &next in &self.body.bb_neighbor_list[bb] {
+                let changed = match &mut entry_states[next] {
+                    Some(entry) => {
+                        let mut changed = false;
+                        for (old, new) in entry.iter_mut().zip(state.iter()) {
+                            if !old.contains(new) {
+                                old.join(new);
+                                changed = true;
+                            }
+                        }
+                        changed
+                    },
+                    entry @ None => {
+                        *entry = Some(state.clone());
+                        true
+                    },
+                };
+                if changed && !in_queue[next] {
+                    in_queue[next] = true;
+                    work_list.push_back(next);
+                }
+            }
+        }
+
+        // Join all taints observed at the sink nodes
+        let mut ret = T::default();
+        let mut reachable_sink = Vec::new();
+        for (id, taint) in hits.iter().enumerate() {
+            if !taint.is_empty() {
+                reachable_sink.push(id);
+                ret.join(taint);
+            }
+        }
+        self.base.set_reachable_sinks(reachable_sink);
+
+        ret
+    }
+
+    fn get_reachable_sinks(&self) -> &Vec<usize> {
+        self.base.get_reachable_sinks()
+    }
+
+    fn sources(&self) -> &[T] {
+        self.base.sources()
+    }
+
+    fn sinks(&self) -> &[bool] {
+        self.base.sinks()
+    }
+
+    fn taint_state(&self) -> &[T] {
+        &self.node_taint
+    }
+}
@@ -0,0 +1,460 @@
+//! Compares the findings of two RuMorph runs.
+//! This is used for the release qualification of the analyzer itself:
+//! `rumorph diff old.toml new.toml` prints the findings that appeared, disappeared,
+//! moved or changed between two runs and exits with a non-zero code on additions.
+//!
+//! Findings are matched by a fingerprint (analyzer, file, reported item) rather than
+//! by their exact location, so that unrelated edits shifting line numbers don't
+//! show up as a removal plus an addition.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+use snafu::{ResultExt, Snafu};
+
+use crate::report::{Report, ReportLevel};
+
+/// Farthest an unmatched finding is paired with an old one of the same fingerprint;
+/// beyond that the old finding is considered fixed and the new one added
+const MAX_LINE_SHIFT: usize = 200;
+
+const DIFF_HELP: &str = r#"Compares the findings of two RuMorph runs
+Usage:
+    rumorph diff <old report> <new report>
+
+Reports can be TOML files written by `RUMORPH_REPORT_PATH`, JSON or SARIF files,
+or directories containing any of them. The exit code is 1 if the new run has
+findings that the old run didn't have, 2 on usage or parsing errors, and 0 otherwise.
+"#;
+
+#[derive(Debug, Snafu)]
+pub enum DiffError {
+    ReadReport { path: PathBuf, source: io::Error },
+    ParseReport { path: PathBuf, message: String },
+}
+
+/// A single finding normalized from any of the supported report formats.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub level: ReportLevel,
+    pub analyzer: String,
+    pub description: String,
+    pub file: String,
+    pub line: Option<usize>,
+}
+
+impl Finding {
+    fn from_report(report: &Report) -> Self {
+        let (file, line) = split_location(report.location());
+        Finding {
+            level: report.level(),
+            analyzer: report.analyzer().to_owned(),
+            description: report.description().to_owned(),
+            file,
+            line,
+        }
+    }
+
+    /// Identifies a finding independently of its line.
+    /// The analyzer flags (e.g. `UnsafeDataflow:/ReadFlow`) and the line numbers embedded
+    /// in the description are not part of the fingerprint.
+    pub fn fingerprint(&self) -> String {
+        let analyzer = self.analyzer.split(':').next().unwrap_or("");
+        format!(
+            "{}|{}|{}",
+            analyzer,
+            self.file,
+            strip_line_marker(&self.description)
+        )
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{} ({}): {}\n    -> {}:{}",
+                self.level, self.analyzer, self.description, self.file, line
+            ),
+            None => write!(
+                f,
+                "{} ({}): {}\n    -> {}",
+                self.level, self.analyzer, self.description, self.file
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReportDiff {
+    pub added: Vec<Finding>,
+    pub removed: Vec<Finding>,
+    /// Same finding, different line
+    /// (either the item itself or the line reported in the description)
+    pub moved: Vec<(Finding, Finding)>,
+    /// Same finding, different level or analyzer flags
+    pub changed: Vec<(Finding, Finding)>,
+    pub unchanged: usize,
+}
+
+impl ReportDiff {
+    pub fn compute(old: Vec<Finding>, new: Vec<Finding>) -> Self {
+        let mut diff = ReportDiff::default();
+
+        // fingerprint -> findings of the old run that are not matched yet
+        let mut old_map: HashMap<String, Vec<Finding>> = HashMap::new();
+        for finding in old {
+            old_map
+                .entry(finding.fingerprint())
+                .or_insert_with(Vec::new)
+                .push(finding);
+        }
+
+        // First pass: exact matches on fingerprint and line
+        let mut unmatched = Vec::new();
+        for finding in new {
+            let candidates = old_map.entry(finding.fingerprint()).or_insert_with(Vec::new);
+            match candidates.iter().position(|old| old.line == finding.line) {
+                Some(pos) => {
+                    let old = candidates.swap_remove(pos);
+                    diff.record_match(old, finding);
+                }
+                None => unmatched.push(finding),
+            }
+        }
+
+        // Second pass: allow line shifts, pairing each finding with the closest old one
+        for finding in unmatched {
+            let candidates = old_map.entry(finding.fingerprint()).or_insert_with(Vec::new);
+            let closest = candidates
+                .iter()
+                .enumerate()
+                .filter_map(|(pos, old)| line_distance(old.line, finding.line).map(|distance| (pos, distance)))
+                .filter(|&(_, distance)| distance <= MAX_LINE_SHIFT)
+                .min_by_key(|&(_, distance)| distance)
+                .map(|(pos, _)| pos);
+            match closest {
+                Some(pos) => {
+                    let old = candidates.swap_remove(pos);
+                    diff.record_match(old, finding);
+                }
+                None => diff.added.push(finding),
+            }
+        }
+
+        for (_, remaining) in old_map {
+            diff.removed.extend(remaining);
+        }
+
+        diff
+    }
+
+    fn record_match(&mut self, old: Finding, new: Finding) {
+        if old.level != new.level || old.analyzer != new.analyzer {
+            self.changed.push((old, new));
+        } else if old.line != new.line || old.description != new.description {
+            self.moved.push((old, new));
+        } else {
+            self.unchanged += 1;
+        }
+    }
+
+    pub fn has_additions(&self) -> bool {
+        !self.added.is_empty()
+    }
+}
+
+impl fmt::Display for ReportDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.added {
+            writeln!(f, "+ {}", finding)?;
+        }
+        for finding in &self.removed {
+            writeln!(f, "- {}", finding)?;
+        }
+        for (old, new) in &self.changed {
+            writeln!(f, "~ {}\n  now {}", old, new)?;
+        }
+        for (old, new) in &self.moved {
+            writeln!(
+                f,
+                "> {} (line {} -> {})",
+                new,
+                display_line(old.line),
+                display_line(new.line)
+            )?;
+        }
+        writeln!(
+            f,
+            "{} added, {} removed, {} changed, {} moved, {} unchanged",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.moved.len(),
+            self.unchanged
+        )
+    }
+}
+
+/// Entry point of `rumorph diff`. `args` are the arguments after `diff`.
+/// Returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    if args.iter().any(|a| a == "--help" || a == "-h") || args.len() != 2 {
+        eprintln!("{}", DIFF_HELP);
+        return 2;
+    }
+
+    let (old, new) = match (load_findings(&args[0]), load_findings(&args[1])) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("rumorph diff: {}", e);
+            return 2;
+        }
+    };
+
+    let diff = ReportDiff::compute(old, new);
+    print!("{}", diff);
+
+    if diff.has_additions() {
+        1
+    } else {
+        0
+    }
+}
+
+/// Loads findings from a report file, or from every report file in a directory.
+pub fn load_findings<P: AsRef<Path>>(path: P) -> Result<Vec<Finding>, DiffError> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)
+            .context(ReadReport { path })?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        let mut findings = Vec::new();
+        for entry in entries {
+            findings.extend(load_findings(&entry)?);
+        }
+        return Ok(findings);
+    }
+
+    let content = fs::read_to_string(path).context(ReadReport { path })?;
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match extension.as_str() {
+        "json" => parse_json(path, &content),
+        "sarif" => parse_sarif(path, &content),
+        // `cargo rumorph` appends `-{kind}-{target}` to the report path,
+        // so files without a known extension are TOML reports
+        _ => parse_toml(path, &content),
+    }
+}
+
+#[derive(Deserialize)]
+struct Reports {
+    #[serde(default)]
+    reports: Vec<Report>,
+}
+
+fn parse_toml(path: &Path, content: &str) -> Result<Vec<Finding>, DiffError> {
+    // `FileLogger` writes raw escape characters for colored sources,
+    // which are not allowed inside TOML strings
+    let content = content.replace('\u{001B}', "\\u001B");
+    let reports: Reports = toml::from_str(&content).or_else(|e| parse_error(path, e))?;
+    Ok(reports.reports.iter().map(Finding::from_report).collect())
+}
+
+fn parse_json(path: &Path, content: &str) -> Result<Vec<Finding>, DiffError> {
+    let value: Value = serde_json::from_str(content).or_else(|e| parse_error(path, e))?;
+
+    // SARIF files are sometimes saved with a plain `.json` extension
+    if value.get("runs").is_some() {
+        return findings_from_sarif(path, &value);
+    }
+
+    let reports = match value {
+        Value::Array(_) => serde_json::from_value::<Vec<Report>>(value),
+        _ => serde_json::from_value::<Reports>(value).map(|r| r.reports),
+    }
+    .or_else(|e| parse_error(path, e))?;
+    Ok(reports.iter().map(Finding::from_report).collect())
+}
+
+fn parse_sarif(path: &Path, content: &str) -> Result<Vec<Finding>, DiffError> {
+    let value: Value = serde_json::from_str(content).or_else(|e| parse_error(path, e))?;
+    findings_from_sarif(path, &value)
+}
+
+fn findings_from_sarif(path: &Path, value: &Value) -> Result<Vec<Finding>, DiffError> {
+    let runs = match value.get("runs").and_then(Value::as_array) {
+        Some(runs) => runs,
+        None => return parse_error(path, "missing `runs` array"),
+    };
+
+    let mut findings = Vec::new();
+    for result in runs
+        .iter()
+        .filter_map(|run| run.get("results").and_then(Value::as_array))
+        .flatten()
+    {
+        let level = match result.get("level").and_then(Value::as_str) {
+            Some("error") => ReportLevel::Error,
+            Some("warning") => ReportLevel::Warning,
+            _ => ReportLevel::Info,
+        };
+        let analyzer = result
+            .get("ruleId")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_owned();
+        let description = result
+            .pointer("/message/text")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_owned();
+        let physical = result.pointer("/locations/0/physicalLocation");
+        let file = physical
+            .and_then(|p| p.pointer("/artifactLocation/uri"))
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_owned();
+        let line = physical
+            .and_then(|p| p.pointer("/region/startLine"))
+            .and_then(Value::as_u64)
+            .map(|l| l as usize);
+
+        findings.push(Finding {
+            level,
+            analyzer,
+            description,
+            file,
+            line,
+        });
+    }
+
+    Ok(findings)
+}
+
+fn parse_error<T>(path: &Path, message: impl ToString) -> Result<T, DiffError> {
+    ParseReport {
+        path,
+        message: message.to_string(),
+    }
+    .fail()
+}
+
+/// Splits a rustc diagnostic location (`src/lib.rs:56:5: 73:6`) into the file and its start line.
+fn split_location(location: &str) -> (String, Option<usize>) {
+    let parts: Vec<&str> = location.split(':').collect();
+    for (idx, part) in parts.iter().enumerate() {
+        if idx > 0 {
+            if let Ok(line) = part.trim().parse::<usize>() {
+                return (parts[..idx].join(":"), Some(line));
+            }
+        }
+    }
+    (location.to_owned(), None)
+}
+
+/// Removes the "at line `N`" suffix that some checkers add to their descriptions.
+fn strip_line_marker(description: &str) -> &str {
+    match description.rfind(" at line `") {
+        Some(pos) => &description[..pos],
+        None => description,
+    }
+}
+
+/// `None` unless both lines are known
+fn line_distance(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.abs_diff(b)),
+        _ => None,
+    }
+}
+
+fn display_line(line: Option<usize>) -> String {
+    match line {
+        Some(line) => line.to_string(),
+        None => String::from("?"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(description: &str, line: Option<usize>) -> Finding {
+        Finding {
+            level: ReportLevel::Error,
+            analyzer: String::from("BrokenLayout:/CAST"),
+            description: description.to_owned(),
+            file: String::from("src/lib.rs"),
+            line,
+        }
+    }
+
+    #[test]
+    fn shifted_finding_is_moved() {
+        let old = vec![finding("Potential broken layout issue in `read`", Some(10))];
+        let new = vec![finding("Potential broken layout issue in `read`", Some(14))];
+        let diff = ReportDiff::compute(old, new);
+        assert_eq!(diff.moved.len(), 1);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert!(!diff.has_additions());
+    }
+
+    #[test]
+    fn new_finding_is_added() {
+        let old = vec![finding("Potential broken layout issue in `read`", Some(10))];
+        let new = vec![
+            finding("Potential broken layout issue in `read`", Some(10)),
+            finding("Potential broken layout issue in `write`", Some(30)),
+        ];
+        let diff = ReportDiff::compute(old, new);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.added.len(), 1);
+        assert!(diff.has_additions());
+    }
+
+    #[test]
+    fn fixed_finding_is_removed() {
+        let old = vec![
+            finding("Potential broken layout issue in `read`", Some(10)),
+            finding("Potential broken layout issue in `write`", Some(30)),
+        ];
+        let new = vec![finding("Potential broken layout issue in `read`", Some(10))];
+        let diff = ReportDiff::compute(old, new);
+        assert_eq!(diff.removed.len(), 1);
+        assert!(!diff.has_additions());
+    }
+
+    #[test]
+    fn distant_or_unlocated_finding_is_not_paired() {
+        let old = vec![
+            finding("Potential broken layout issue in `read`", Some(10)),
+            finding("Potential broken layout issue in `read`", None),
+        ];
+        let new = vec![
+            finding("Potential broken layout issue in `read`", Some(10 + MAX_LINE_SHIFT + 1)),
+            finding("Potential broken layout issue in `read`", Some(20)),
+        ];
+        let diff = ReportDiff::compute(old, new);
+        // the finding at line 20 is closer to line 10, the unlocated one isn't paired at all
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].line, None);
+    }
+}
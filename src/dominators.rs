@@ -0,0 +1,211 @@
+//! Dominator trees of basic block graphs (Cooper, Harvey and Kennedy, "A Simple, Fast
+//! Dominance Algorithm"). Post-dominators are the dominators of the reversed graph, rooted
+//! at a virtual exit that every block without successors leads to.
+
+/// Immediate dominators of a graph given as successor lists
+#[derive(Debug, Clone)]
+pub struct DominatorTree {
+    root: usize,
+    /// `None` for the root and for nodes the root can't reach
+    idom: Vec<Option<usize>>,
+    /// post-order number of every reachable node, used to walk up the tree
+    post_order: Vec<usize>,
+}
+
+const UNREACHED: usize = usize::MAX;
+
+impl DominatorTree {
+    pub fn compute(successors: &[Vec<usize>], root: usize) -> Self {
+        let len = successors.len();
+        let mut predecessors = vec![Vec::new(); len];
+        for (from, tos) in successors.iter().enumerate() {
+            for &to in tos {
+                predecessors[to].push(from);
+            }
+        }
+
+        // iterative DFS, a node is numbered once all its successors are
+        let mut post_order = vec![UNREACHED; len];
+        let mut order = Vec::with_capacity(len);
+        let mut visited = vec![false; len];
+        let mut stack = vec![(root, 0)];
+        visited[root] = true;
+        while let Some((node, next)) = stack.pop() {
+            if let Some(&succ) = successors[node].get(next) {
+                stack.push((node, next + 1));
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                post_order[node] = order.len();
+                order.push(node);
+            }
+        }
+
+        let mut idom = vec![UNREACHED; len];
+        idom[root] = root;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in order.iter().rev().filter(|&&node| node != root) {
+                let mut new_idom = UNREACHED;
+                for &pred in &predecessors[node] {
+                    if idom[pred] == UNREACHED {
+                        continue;
+                    }
+                    new_idom = if new_idom == UNREACHED {
+                        pred
+                    } else {
+                        Self::intersect(&idom, &post_order, pred, new_idom)
+                    };
+                }
+                if new_idom != idom[node] {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        DominatorTree {
+            root,
+            idom: idom
+                .iter()
+                .enumerate()
+                .map(|(node, &dom)| (node != root && dom != UNREACHED).then_some(dom))
+                .collect(),
+            post_order,
+        }
+    }
+
+    fn intersect(idom: &[usize], post_order: &[usize], mut a: usize, mut b: usize) -> usize {
+        while a != b {
+            while post_order[a] < post_order[b] {
+                a = idom[a];
+            }
+            while post_order[b] < post_order[a] {
+                b = idom[b];
+            }
+        }
+        a
+    }
+
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    pub fn is_reachable(&self, node: usize) -> bool {
+        node == self.root || self.idom[node].is_some()
+    }
+
+    pub fn immediate_dominator(&self, node: usize) -> Option<usize> {
+        self.idom[node]
+    }
+
+    /// `node` followed by its dominators up to the root; empty if `node` is unreachable
+    pub fn dominators(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        let start = self.is_reachable(node).then_some(node);
+        std::iter::successors(start, move |&node| self.idom[node])
+    }
+
+    /// Whether every path from the root to `b` goes through `a` (every node dominates itself)
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        self.is_reachable(a) && self.is_reachable(b) && {
+            // a node is visited after everything it dominates
+            self.post_order[a] >= self.post_order[b] && self.dominators(b).any(|node| node == a)
+        }
+    }
+
+    /// Nodes immediately dominated by `node`
+    pub fn children(&self, node: usize) -> Vec<usize> {
+        self.idom
+            .iter()
+            .enumerate()
+            .filter(|(_, &dom)| dom == Some(node))
+            .map(|(child, _)| child)
+            .collect()
+    }
+}
+
+/// Post-dominators of a graph with `successors`; node `successors.len()` is the virtual exit
+/// that follows every node without successors
+pub fn post_dominators(successors: &[Vec<usize>]) -> DominatorTree {
+    let exit = successors.len();
+    let mut reversed = vec![Vec::new(); exit + 1];
+    for (from, tos) in successors.iter().enumerate() {
+        if tos.is_empty() {
+            reversed[exit].push(from);
+        }
+        for &to in tos {
+            reversed[to].push(from);
+        }
+    }
+    DominatorTree::compute(&reversed, exit)
+}
+
+/// A loop whose `header` dominates every block of the loop
+#[derive(Debug, Clone)]
+pub struct NaturalLoop {
+    pub header: usize,
+    /// sources of the back edges to `header`
+    pub latches: Vec<usize>,
+    /// blocks of the loop including `header`, in ascending order
+    pub blocks: Vec<usize>,
+}
+
+impl NaturalLoop {
+    pub fn contains(&self, node: usize) -> bool {
+        self.blocks.binary_search(&node).is_ok()
+    }
+}
+
+/// Natural loops of a graph, one per header; the back edges sharing a header are merged
+pub fn natural_loops(successors: &[Vec<usize>], dominators: &DominatorTree) -> Vec<NaturalLoop> {
+    let mut predecessors = vec![Vec::new(); successors.len()];
+    for (from, tos) in successors.iter().enumerate() {
+        for &to in tos {
+            predecessors[to].push(from);
+        }
+    }
+
+    let mut loops: Vec<NaturalLoop> = Vec::new();
+    for (from, tos) in successors.iter().enumerate() {
+        for &header in tos {
+            if !dominators.dominates(header, from) {
+                continue;
+            }
+            match loops.iter_mut().find(|lp| lp.header == header) {
+                Some(lp) => lp.latches.push(from),
+                None => loops.push(NaturalLoop {
+                    header,
+                    latches: vec![from],
+                    blocks: Vec::new(),
+                }),
+            }
+        }
+    }
+
+    for lp in &mut loops {
+        // everything that reaches a latch without going through the header
+        let mut in_loop = vec![false; successors.len()];
+        in_loop[lp.header] = true;
+        let mut work_list = Vec::new();
+        for &latch in &lp.latches {
+            if !in_loop[latch] {
+                in_loop[latch] = true;
+                work_list.push(latch);
+            }
+        }
+        while let Some(node) = work_list.pop() {
+            for &pred in &predecessors[node] {
+                if !in_loop[pred] && dominators.is_reachable(pred) {
+                    in_loop[pred] = true;
+                    work_list.push(pred);
+                }
+            }
+        }
+        lp.blocks = (0..successors.len()).filter(|&node| in_loop[node]).collect();
+    }
+    loops.sort_by_key(|lp| lp.header);
+    loops
+}
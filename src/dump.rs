@@ -0,0 +1,140 @@
+//! GraphViz (DOT) export of translated bodies, enabled with `-Zrumorph-dump-graph=<pattern>`.
+//! For every function whose path contains the pattern, each checker writes
+//! `<dir>/<fn path>.<checker>.dot` with the place graph and the taint it saw, and
+//! `<dir>/<fn path>.cfg.dot` with the basic block graph. `<dir>` is `RuMorphConfig::graph_dir`,
+//! `rumorph-graphs` by default.
+
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::Path;
+
+use crate::graph::{GraphTaint, Scc, TaintEngine};
+use crate::ir;
+use crate::progress_info;
+
+pub const DEFAULT_GRAPH_DIR: &str = "rumorph-graphs";
+
+/// Longest statement summary shown in a CFG node
+const STATEMENT_SUMMARY_LIMIT: usize = 60;
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\l")
+}
+
+fn is_untainted<T: GraphTaint>(taint: &T) -> bool {
+    T::default().contains(taint)
+}
+
+fn summarize(text: String) -> String {
+    if text.chars().count() > STATEMENT_SUMMARY_LIMIT {
+        let mut short: String = text.chars().take(STATEMENT_SUMMARY_LIMIT).collect();
+        short.push_str("...");
+        short
+    } else {
+        text
+    }
+}
+
+/// Place graph of `body`: sources are red, sinks are boxes, nodes reached by taint are
+/// filled, and the members of a non-trivial SCC are grouped in a cluster.
+pub fn place_graph_dot<T: GraphTaint + fmt::Debug>(
+    body: &ir::Body<'_>,
+    engine: &dyn TaintEngine<T>,
+) -> String {
+    let mut dot = String::from("digraph places {\n    node [fontname=monospace];\n");
+    let (sources, sinks, taints) = (engine.sources(), engine.sinks(), engine.taint_state());
+
+    for id in 0..body.place_index.len() {
+        let node = body.place_index.node(id);
+        let mut label = format!("{}: {:?}", id, node);
+        if !is_untainted(&taints[id]) {
+            label.push_str(&format!("\n{:?}", taints[id]));
+        }
+        let shape = if sinks[id] { "box" } else { "ellipse" };
+        let color = if is_untainted(&sources[id]) { "black" } else { "red" };
+        let style = if is_untainted(&taints[id]) { "solid" } else { "filled" };
+        let _ = writeln!(
+            dot,
+            "    n{} [label=\"{}\", shape={}, color={}, style={}, fillcolor=lightpink];",
+            id,
+            escape(&label),
+            shape,
+            color,
+            style
+        );
+    }
+
+    let scc = Scc::construct(body);
+    for group in 0..scc.num_groups() {
+        let nodes = scc.nodes_in_group(group);
+        if nodes.len() > 1 {
+            let _ = writeln!(dot, "    subgraph cluster_scc{} {{\n        style=dashed;", group);
+            for node in nodes {
+                let _ = writeln!(dot, "        n{};", node);
+            }
+            dot.push_str("    }\n");
+        }
+    }
+
+    for (from, tos) in body.place_neighbor_list.iter().enumerate() {
+        for to in tos {
+            let _ = writeln!(dot, "    n{} -> n{};", from, to);
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Basic block graph of `body`, one node per block listing its statements and terminator
+pub fn cfg_dot(body: &ir::Body<'_>) -> String {
+    let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+
+    for (bb, block) in body.basic_blocks.iter().enumerate() {
+        let mut label = format!("bb{}{}\n", bb, if block.is_cleanup { " (cleanup)" } else { "" });
+        for statement in &block.statements {
+            label.push_str(&summarize(format!("{:?}", statement)));
+            label.push('\n');
+        }
+        label.push_str(&summarize(format!("{:?}", block.terminator.original.kind)));
+        label.push('\n');
+        let _ = writeln!(dot, "    bb{} [label=\"{}\"];", bb, escape(&label));
+    }
+
+    for (from, tos) in body.bb_neighbor_list.iter().enumerate() {
+        for to in tos {
+            let _ = writeln!(dot, "    bb{} -> bb{};", from, to);
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Writes the place graph seen by `checker` and the CFG of the function `fn_path` to `dir`
+pub fn write_graphs<T: GraphTaint + fmt::Debug>(
+    dir: &Path,
+    fn_path: &str,
+    checker: &str,
+    body: &ir::Body<'_>,
+    engine: &dyn TaintEngine<T>,
+) {
+    let file_stem: String = fn_path
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+
+    let result: std::io::Result<()> = try {
+        fs::create_dir_all(dir)?;
+        fs::write(
+            dir.join(format!("{}.{}.dot", file_stem, checker)),
+            place_graph_dot(body, engine),
+        )?;
+        fs::write(dir.join(format!("{}.cfg.dot", file_stem)), cfg_dot(body))?;
+    };
+    match result {
+        Ok(()) => progress_info!("graphs of `{}` written to {}", fn_path, dir.display()),
+        Err(e) => warn!("Failed to write the graphs of `{}`: {}", fn_path, e),
+    }
+}
@@ -1,21 +1,28 @@
-use std::{cmp::min, collections::VecDeque, collections::HashSet};
+use std::{cell::OnceCell, cell::RefCell, cmp::min, collections::VecDeque};
+
+use rustc_index::bit_set::BitSet;
 
 use crate::ir;
 
 pub trait Graph {
     fn len(&self) -> usize;
-    fn next(&self, id: usize) -> Vec<usize>;
+    fn next(&self, id: usize) -> &[usize];
+    fn prev(&self, id: usize) -> &[usize];
 }
 
 impl<'tcx> Graph for ir::Body<'tcx> {
     fn len(&self) -> usize {
-        // the count of all vars used at MIR level
-        self.local_decls.len()
+        // the count of all place nodes, starting with the plain locals
+        self.place_index.len()
     }
 
-    fn next(&self, id: usize) -> Vec<usize> {
+    fn next(&self, id: usize) -> &[usize] {
         // next places of current place id
-        unsafe { self.place_neighbor_list.get_unchecked(id).to_owned() }
+        unsafe { self.place_neighbor_list.get_unchecked(id) }
+    }
+
+    fn prev(&self, id: usize) -> &[usize] {
+        &self.place_predecessor_list[id]
     }
 }
 
@@ -23,6 +30,86 @@ pub trait GraphTaint: Clone + Default {
     fn is_empty(&self) -> bool;
     fn contains(&self, taint: &Self) -> bool;
     fn join(&mut self, taint: &Self);
+    fn remove(&mut self, taint: &Self);
+}
+
+/// How a sanitizer node filters the taint flowing into it
+#[derive(Clone)]
+enum Sanitizer<T> {
+    None,
+    /// blocks every taint
+    All,
+    /// removes only the given taint
+    Mask(T),
+}
+
+/// Interface shared by the flow-insensitive `TaintAnalyzer` and
+/// the flow-sensitive `crate::dataflow::FlowTaintAnalyzer`, so checkers can pick either one.
+pub trait TaintEngine<T: GraphTaint> {
+    fn mark_source(&mut self, id: usize, taint: &T);
+    fn clear_source(&mut self, id: usize);
+    fn is_reachable(&self, start_id: usize, end_id: usize) -> bool;
+    fn is_reachable_from_any(&self, start_ids: &[usize], end_id: usize) -> bool;
+    fn sources_reaching(&self, sink_id: usize) -> Vec<usize>;
+    fn mark_sink(&mut self, id: usize);
+    fn mark_sinks(&mut self, ids: Vec<usize>);
+    fn unmark_sink(&mut self, id: usize);
+    fn mark_at_once(&mut self, id: usize, taint: &T);
+    fn mark_sanitizer(&mut self, id: usize);
+    fn mark_sanitizer_mask(&mut self, id: usize, mask: &T);
+    fn clear(&mut self);
+    fn propagate(&mut self) -> T;
+    fn get_reachable_sinks(&self) -> &Vec<usize>;
+    fn sources(&self) -> &[T];
+    fn sinks(&self) -> &[bool];
+    /// Taint of every node after the last `propagate`
+    fn taint_state(&self) -> &[T];
+}
+
+/// Buffers shared by the reachability queries, so a query doesn't allocate
+struct Scratch {
+    visited: BitSet<usize>,
+    work_list: Vec<usize>,
+}
+
+impl Scratch {
+    fn new(len: usize) -> Self {
+        Scratch {
+            visited: BitSet::new_empty(len),
+            work_list: Vec::new(),
+        }
+    }
+
+    /// Marks in `visited` every node reachable from `starts` over `step`, `starts` included.
+    /// Stops early and returns true once `target` is visited.
+    fn search<'g>(
+        &mut self,
+        starts: &[usize],
+        step: impl Fn(usize) -> &'g [usize],
+        target: Option<usize>,
+    ) -> bool {
+        self.visited.clear();
+        self.work_list.clear();
+        for &start in starts {
+            if self.visited.insert(start) {
+                self.work_list.push(start);
+            }
+        }
+        if let Some(target) = target && self.visited.contains(target) {
+            return true;
+        }
+        while let Some(curr) = self.work_list.pop() {
+            for &next in step(curr) {
+                if self.visited.insert(next) {
+                    if Some(next) == target {
+                        return true;
+                    }
+                    self.work_list.push(next);
+                }
+            }
+        }
+        false
+    }
 }
 
 pub struct TaintAnalyzer<'a, G: Graph, T: GraphTaint> {
@@ -30,7 +117,12 @@ pub struct TaintAnalyzer<'a, G: Graph, T: GraphTaint> {
     len: usize,
     sources: Vec<T>,
     sinks: Vec<bool>,
+    sanitizers: Vec<Sanitizer<T>>,
     reachable_sink: Vec<usize>,
+    taint_state: Vec<T>,
+    /// condensation used by `propagate`, built on first use
+    scc: OnceCell<Scc<'a, G>>,
+    scratch: RefCell<Scratch>,
 }
 
 impl<'a, G: Graph, T: GraphTaint> TaintAnalyzer<'a, G, T> {
@@ -41,7 +133,11 @@ impl<'a, G: Graph, T: GraphTaint> TaintAnalyzer<'a, G, T> {
             len: graph_len,
             sources: vec![T::default(); graph_len],
             sinks: vec![false; graph_len],
+            sanitizers: vec![Sanitizer::None; graph_len],
             reachable_sink: Vec::new(),
+            taint_state: vec![T::default(); graph_len],
+            scc: OnceCell::new(),
+            scratch: RefCell::new(Scratch::new(graph_len)),
         }
     }
 
@@ -54,55 +150,60 @@ impl<'a, G: Graph, T: GraphTaint> TaintAnalyzer<'a, G, T> {
     }
 
     pub fn clear_source(&mut self, id: usize) {
-        // use bfs to clean the tainted source
-        let mut work_list = VecDeque::new();
-        let mut visited = HashSet::new();
-
-        work_list.push_back(id);
-        visited.insert(id);
-        while let Some(current) = work_list.pop_front() {
-            for next in self.graph.next(current) {
-                if !visited.contains(&next) {
-                    work_list.push_back(next);
-                    visited.insert(next);
-                }
-            }
-        }
-
-        for v in visited.iter() {
-            self.sources[*v] = T::default();
+        // clean the tainted source and everything it flows into
+        let graph = self.graph;
+        let scratch = self.scratch.get_mut();
+        scratch.search(&[id], |curr| graph.next(curr), None);
+        for v in scratch.visited.iter() {
+            self.sources[v] = T::default();
         }
     }
 
     pub fn is_reachable(&self, start_id: usize, end_id: usize) -> bool {
-        if start_id == end_id {
-            return true;
-        }
-        
-        let mut work_list = VecDeque::new();
-        let mut visited = HashSet::new();
-
-        work_list.push_back(start_id);
-        visited.insert(start_id);
-        while let Some(curr) = work_list.pop_front() {
-            for next in self.graph.next(curr) {
-                if next == end_id {
-                    return true;
-                }
-                if !visited.contains(&next) {
-                    work_list.push_back(next);
-                    visited.insert(next);
-                }
-            }
-        }
+        let graph = self.graph;
+        self.scratch
+            .borrow_mut()
+            .search(&[start_id], |curr| graph.next(curr), Some(end_id))
+    }
 
-        false
+    /// One traversal from all the start nodes instead of an `is_reachable` call per start node
+    pub fn is_reachable_from_any(&self, start_ids: &[usize], end_id: usize) -> bool {
+        let graph = self.graph;
+        self.scratch
+            .borrow_mut()
+            .search(start_ids, |curr| graph.next(curr), Some(end_id))
+    }
+
+    /// Marked sources from which `sink_id` is reachable
+    pub fn sources_reaching(&self, sink_id: usize) -> Vec<usize> {
+        let graph = self.graph;
+        let mut scratch = self.scratch.borrow_mut();
+        scratch.search(&[sink_id], |curr| graph.prev(curr), None);
+        scratch
+            .visited
+            .iter()
+            .filter(|&id| !self.sources[id].is_empty())
+            .collect()
+    }
+
+    /// Nodes with a path to `end_id`, `end_id` included, found over `Graph::prev`
+    pub fn nodes_reaching(&self, end_id: usize) -> Vec<bool> {
+        let graph = self.graph;
+        let mut scratch = self.scratch.borrow_mut();
+        scratch.search(&[end_id], |curr| graph.prev(curr), None);
+        (0..self.len).map(|id| scratch.visited.contains(id)).collect()
     }
 
     pub fn mark_sink(&mut self, id: usize) {
         self.sinks[id] = true;
     }
 
+    pub fn mark_sinks(&mut self, ids: impl IntoIterator<Item = usize>) {
+        for id in ids {
+            self.sinks[id] = true;
+        }
+    }
+
     pub fn unmark_sink(&mut self, id: usize) {
         self.sinks[id] = false;
     }
@@ -112,35 +213,98 @@ impl<'a, G: Graph, T: GraphTaint> TaintAnalyzer<'a, G, T> {
         self.sinks[id] = true;
     }
 
-    // Unmark all sources and sinks
+    /// Taint flowing into `id` is dropped, so only the nodes downstream of the check are clean.
+    /// A source marked on `id` itself is kept.
+    pub fn mark_sanitizer(&mut self, id: usize) {
+        self.sanitizers[id] = Sanitizer::All;
+    }
+
+    /// Like `mark_sanitizer`, but only removes `mask` from the taint flowing into `id`
+    pub fn mark_sanitizer_mask(&mut self, id: usize, mask: &T) {
+        self.sanitizers[id] = match std::mem::replace(&mut self.sanitizers[id], Sanitizer::None) {
+            Sanitizer::All => Sanitizer::All,
+            Sanitizer::Mask(mut old) => {
+                old.join(mask);
+                Sanitizer::Mask(old)
+            },
+            Sanitizer::None => Sanitizer::Mask(mask.clone()),
+        };
+    }
+
+    /// The part of `taint` that passes into node `id`
+    pub(crate) fn sanitize(&self, id: usize, taint: &T) -> T {
+        match &self.sanitizers[id] {
+            Sanitizer::None => taint.clone(),
+            Sanitizer::All => T::default(),
+            Sanitizer::Mask(mask) => {
+                let mut taint = taint.clone();
+                taint.remove(mask);
+                taint
+            },
+        }
+    }
+
+    // Unmark all sources, sinks and sanitizers
     pub fn clear(&mut self) {
         self.sources = vec![T::default(); self.len];
         self.sinks = vec![false; self.len];
+        self.sanitizers = vec![Sanitizer::None; self.len];
     }
 
     // Checks reachability between `self.sources` & `self.sinks`.
+    // Taint is pushed through the SCC condensation in topological order, so every group is
+    // settled once, after all the groups flowing into it.
     pub fn propagate(&mut self) -> T {
+        let graph = self.graph;
+        let scc = self.scc.get_or_init(|| Scc::construct(graph));
         let mut taint_state = vec![T::default(); self.len];
-        let mut work_list = VecDeque::new();
 
-        // Initialize work list
         for id in 0..self.len {
             if !self.sources[id].is_empty() {
                 taint_state[id].join(&self.sources[id]);
-                work_list.push_back(id);
             }
         }
 
-        // Breadth-first propagation
-        while let Some(current) = work_list.pop_front() {
-            for next in self.graph.next(current) {
-                let mut next_state = std::mem::take(&mut taint_state[next]);
-                let taint = &taint_state[current];
-                if !next_state.contains(taint) {
-                    next_state.join(taint);
-                    work_list.push_back(next);
+        for group in scc.topological_order() {
+            let nodes = scc.nodes_in_group(group);
+            if nodes.len() > 1 {
+                let sanitized = nodes
+                    .iter()
+                    .any(|&id| !matches!(self.sanitizers[id], Sanitizer::None));
+                if sanitized {
+                    // sanitizers filter taint inside the cycle, iterate to a fixpoint
+                    let mut work_list: VecDeque<usize> = nodes.iter().copied().collect();
+                    while let Some(current) = work_list.pop_front() {
+                        for &next in graph.next(current) {
+                            if scc.group_of_node(next) != group {
+                                continue;
+                            }
+                            let taint = self.sanitize(next, &taint_state[current]);
+                            if !taint_state[next].contains(&taint) {
+                                taint_state[next].join(&taint);
+                                work_list.push_back(next);
+                            }
+                        }
+                    }
+                } else {
+                    // every node of the cycle reaches every other one
+                    let mut joined = T::default();
+                    for &id in nodes {
+                        joined.join(&taint_state[id]);
+                    }
+                    for &id in nodes {
+                        taint_state[id].join(&joined);
+                    }
+                }
+            }
+
+            for &current in nodes {
+                for &next in graph.next(current) {
+                    if scc.group_of_node(next) != group {
+                        let taint = self.sanitize(next, &taint_state[current]);
+                        taint_state[next].join(&taint);
+                    }
                 }
-                taint_state[next] = next_state;
             }
         }
 
@@ -152,13 +316,100 @@ impl<'a, G: Graph, T: GraphTaint> TaintAnalyzer<'a, G, T> {
                 ret.join(&taint_state[id]);
             }
         }
-        
+        self.taint_state = taint_state;
+
         return ret;
     }
 
     pub fn get_reachable_sinks(&self) -> &Vec<usize> {
         &self.reachable_sink
     }
+
+    pub(crate) fn sources(&self) -> &[T] {
+        &self.sources
+    }
+
+    pub(crate) fn sinks(&self) -> &[bool] {
+        &self.sinks
+    }
+
+    pub fn taint_state(&self) -> &[T] {
+        &self.taint_state
+    }
+
+    pub(crate) fn set_reachable_sinks(&mut self, reachable_sink: Vec<usize>) {
+        self.reachable_sink = reachable_sink;
+    }
+}
+
+impl<'a, G: Graph, T: GraphTaint> TaintEngine<T> for TaintAnalyzer<'a, G, T> {
+    fn mark_source(&mut self, id: usize, taint: &T) {
+        TaintAnalyzer::mark_source(self, id, taint)
+    }
+
+    fn clear_source(&mut self, id: usize) {
+        TaintAnalyzer::clear_source(self, id)
+    }
+
+    fn is_reachable(&self, start_id: usize, end_id: usize) -> bool {
+        TaintAnalyzer::is_reachable(self, start_id, end_id)
+    }
+
+    fn is_reachable_from_any(&self, start_ids: &[usize], end_id: usize) -> bool {
+        TaintAnalyzer::is_reachable_from_any(self, start_ids, end_id)
+    }
+
+    fn sources_reaching(&self, sink_id: usize) -> Vec<usize> {
+        TaintAnalyzer::sources_reaching(self, sink_id)
+    }
+
+    fn mark_sink(&mut self, id: usize) {
+        TaintAnalyzer::mark_sink(self, id)
+    }
+
+    fn mark_sinks(&mut self, ids: Vec<usize>) {
+        TaintAnalyzer::mark_sinks(self, ids)
+    }
+
+    fn unmark_sink(&mut self, id: usize) {
+        TaintAnalyzer::unmark_sink(self, id)
+    }
+
+    fn mark_at_once(&mut self, id: usize, taint: &T) {
+        TaintAnalyzer::mark_at_once(self, id, taint)
+    }
+
+    fn mark_sanitizer(&mut self, id: usize) {
+        TaintAnalyzer::mark_sanitizer(self, id)
+    }
+
+    fn mark_sanitizer_mask(&mut self, id: usize, mask: &T) {
+        TaintAnalyzer::mark_sanitizer_mask(self, id, mask)
+    }
+
+    fn clear(&mut self) {
+        TaintAnalyzer::clear(self)
+    }
+
+    fn propagate(&mut self) -> T {
+        TaintAnalyzer::propagate(self)
+    }
+
+    fn get_reachable_sinks(&self) -> &Vec<usize> {
+        TaintAnalyzer::get_reachable_sinks(self)
+    }
+
+    fn sources(&self) -> &[T] {
+        TaintAnalyzer::sources(self)
+    }
+
+    fn sinks(&self) -> &[bool] {
+        TaintAnalyzer::sinks(self)
+    }
+
+    fn taint_state(&self) -> &[T] {
+        TaintAnalyzer::taint_state(self)
+    }
 }
 
 /// Strongly Connected Component (SCC) using Tarjan's algorithm
@@ -172,12 +423,16 @@ pub struct Scc<'a, G: Graph> {
     group_graph: Vec<Vec<usize>>,
 }
 
+/// `group_of_node` value of the nodes that are not assigned to a group yet
+const NO_GROUP: usize = usize::MAX;
+
 /// Temporary state variable used in SCC construction
 struct SccConstructionState {
     // intermediate state
     current_index: usize,
     stack: Vec<usize>,
     index: Vec<usize>,
+    low_link: Vec<usize>,
     // result
     group_of_node: Vec<usize>,
     nodes_in_group: Vec<Vec<usize>>,
@@ -189,7 +444,8 @@ impl SccConstructionState {
             current_index: 0,
             stack: Vec::new(),
             index: vec![0; size],
-            group_of_node: vec![0; size],
+            low_link: vec![0; size],
+            group_of_node: vec![NO_GROUP; size],
             nodes_in_group: Vec::new(),
         }
     }
@@ -197,6 +453,8 @@ impl SccConstructionState {
     fn assign_id(&mut self, node: usize) {
         self.current_index += 1;
         self.index[node] = self.current_index;
+        self.low_link[node] = self.current_index;
+        self.stack.push(node);
     }
 }
 
@@ -230,7 +488,7 @@ impl<'a, G: Graph> Scc<'a, G> {
         let num_group = state.nodes_in_group.len();
         let mut group_graph = vec![Vec::new(); num_group];
         for from in 0..num_node {
-            for to in graph.next(from).into_iter() {
+            for &to in graph.next(from) {
                 let from_group = state.group_of_node[from];
                 let to_group = state.group_of_node[to];
                 if from_group != to_group {
@@ -260,52 +518,72 @@ impl<'a, G: Graph> Scc<'a, G> {
         }
     }
 
-    // returns the lowest reachable node
-    fn traverse(graph: &'a G, state: &mut SccConstructionState, node: usize) -> usize {
-        state.assign_id(node);
-        state.stack.push(node);
+    // Tarjan's algorithm with an explicit stack of (node, next successor to visit),
+    // so deep graphs don't overflow the call stack
+    fn traverse(graph: &'a G, state: &mut SccConstructionState, root: usize) {
+        state.assign_id(root);
+        let mut call_stack = vec![(root, 0)];
+
+        while let Some(top) = call_stack.last_mut() {
+            let (node, next_idx) = *top;
+            if let Some(&next) = graph.next(node).get(next_idx) {
+                top.1 += 1;
+                if state.index[next] == 0 {
+                    // not visited yet
+                    state.assign_id(next);
+                    call_stack.push((next, 0));
+                } else if state.group_of_node[next] == NO_GROUP {
+                    // already in stack
+                    state.low_link[node] = min(state.low_link[node], state.index[next]);
+                }
+                continue;
+            }
 
-        let mut low_link = state.index[node];
-        for next in graph.next(node).into_iter() {
-            if state.index[next] == 0 {
-                // not visited yet
-                low_link = min(low_link, Scc::traverse(graph, state, next));
-            } else if state.group_of_node[next] == 0 {
-                // already in stack
-                low_link = min(low_link, state.index[next]);
+            call_stack.pop();
+            if let Some(&(parent, _)) = call_stack.last() {
+                state.low_link[parent] = min(state.low_link[parent], state.low_link[node]);
             }
-        }
 
-        // SCC boundary found
-        if low_link == state.index[node] {
-            // all nodes in the stack after this node belongs to the same group
-            let mut new_group = Vec::new();
-            let group_num = state.nodes_in_group.len() + 1;
-            loop {
-                let now = state.stack.pop().unwrap();
-                state.group_of_node[now] = group_num;
-                new_group.push(now);
-
-                if now == node {
-                    break;
+            // SCC boundary found
+            if state.low_link[node] == state.index[node] {
+                // all nodes in the stack after this node belongs to the same group
+                let mut new_group = Vec::new();
+                let group_num = state.nodes_in_group.len();
+                loop {
+                    let now = state.stack.pop().unwrap();
+                    state.group_of_node[now] = group_num;
+                    new_group.push(now);
+
+                    if now == node {
+                        break;
+                    }
                 }
+                state.nodes_in_group.push(new_group);
             }
-            state.nodes_in_group.push(new_group);
         }
-
-        low_link
     }
 
-    fn topological_dfs(&self, state: &mut SccTopologicalOrderState, group: usize) {
-        state.visited[group] = true;
-        state.order.push(group);
-        for &next_group in self.next_groups(group).iter() {
-            if !state.visited[next_group] {
-                self.topological_dfs(state, next_group)
+    fn topological_dfs(&self, state: &mut SccTopologicalOrderState, root: usize) {
+        state.visited[root] = true;
+        let mut call_stack = vec![(root, 0)];
+
+        while let Some(top) = call_stack.last_mut() {
+            let (group, next_idx) = *top;
+            if let Some(&next_group) = self.next_groups(group).get(next_idx) {
+                top.1 += 1;
+                if !state.visited[next_group] {
+                    state.visited[next_group] = true;
+                    call_stack.push((next_group, 0));
+                }
+            } else {
+                call_stack.pop();
+                // post-order: a group is pushed after every group it reaches
+                state.order.push(group);
             }
         }
     }
 
+    /// Groups ordered so that each group comes before the groups it reaches
     pub fn topological_order(&self) -> Vec<usize> {
         let num_group = self.group_graph.len();
         let mut state = SccTopologicalOrderState::new(num_group);
@@ -336,4 +614,8 @@ impl<'a, G: Graph> Scc<'a, G> {
     pub fn next_groups(&self, group_idx: usize) -> &[usize] {
         &self.group_graph[group_idx]
     }
+
+    pub fn num_groups(&self) -> usize {
+        self.nodes_in_group.len()
+    }
 }
\ No newline at end of file
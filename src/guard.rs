@@ -0,0 +1,396 @@
+//! Comparison facts established by `SwitchInt` branches.
+//! A fact `a >= b` holds in a block if every path to it takes a branch where the comparison
+//! was true and neither side was reassigned since. Operands are compared by the place they
+//! were copied from, so `_5 = copy _1; _6 = Ge(move _5, copy _2)` yields a fact about `_1` and `_2`,
+//! as long as `_1` keeps its value for the whole body; a copy of a reassigned local stays as is.
+//! A local loses its facts when it is mutably borrowed, its address is taken or it is moved
+//! into a call; writes through references are not tracked otherwise.
+
+use std::collections::VecDeque;
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::{self, BinOp, BorrowKind, Operand, Rvalue, StatementKind, UnOp};
+use rustc_middle::ty::{TyCtxt, TyKind};
+
+use crate::ir;
+use crate::paths;
+use crate::prelude::*;
+
+/// Side of a comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'tcx> {
+    Place(mir::Place<'tcx>),
+    /// `place.len()` of a slice, array or collection
+    Len(mir::Place<'tcx>),
+    Const(mir::ConstantKind<'tcx>),
+}
+
+impl<'tcx> Value<'tcx> {
+    fn local(&self) -> Option<mir::Local> {
+        match self {
+            Value::Place(place) | Value::Len(place) => Some(place.local),
+            Value::Const(_) => None,
+        }
+    }
+}
+
+/// `lhs op rhs`, with `op` one of `Lt`, `Le`, `Eq`, `Ne`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fact<'tcx> {
+    lhs: Value<'tcx>,
+    op: BinOp,
+    rhs: Value<'tcx>,
+}
+
+impl<'tcx> Fact<'tcx> {
+    fn new(lhs: Value<'tcx>, op: BinOp, rhs: Value<'tcx>) -> Option<Self> {
+        // `Gt`/`Ge` are stored as swapped `Lt`/`Le`
+        match op {
+            BinOp::Lt | BinOp::Le | BinOp::Eq | BinOp::Ne => Some(Fact { lhs, op, rhs }),
+            BinOp::Gt => Some(Fact { lhs: rhs, op: BinOp::Lt, rhs: lhs }),
+            BinOp::Ge => Some(Fact { lhs: rhs, op: BinOp::Le, rhs: lhs }),
+            _ => None,
+        }
+    }
+
+    fn negate(&self) -> Self {
+        let (lhs, rhs) = (self.lhs.clone(), self.rhs.clone());
+        match self.op {
+            BinOp::Lt => Fact { lhs: rhs, op: BinOp::Le, rhs: lhs },
+            BinOp::Le => Fact { lhs: rhs, op: BinOp::Lt, rhs: lhs },
+            BinOp::Eq => Fact { lhs, op: BinOp::Ne, rhs },
+            _ => Fact { lhs, op: BinOp::Eq, rhs },
+        }
+    }
+
+    fn mentions(&self, local: mir::Local) -> bool {
+        self.lhs.local() == Some(local) || self.rhs.local() == Some(local)
+    }
+
+    /// Whether the fact can change when `local` is mutated in place without changing its
+    /// length, e.g. through a `&mut [T]`
+    fn mentions_contents(&self, local: mir::Local) -> bool {
+        let is_contents = |value: &Value<'tcx>| matches!(value, Value::Place(place) if place.local == local);
+        is_contents(&self.lhs) || is_contents(&self.rhs)
+    }
+
+    fn implies(&self, other: &Fact<'tcx>) -> bool {
+        let same = self.lhs == other.lhs && self.rhs == other.rhs;
+        let swapped = self.lhs == other.rhs && self.rhs == other.lhs;
+        match (self.op, other.op) {
+            (BinOp::Eq, BinOp::Eq | BinOp::Ne) | (BinOp::Ne, BinOp::Ne) => {
+                (same || swapped) && self.op == other.op
+            },
+            (BinOp::Eq, BinOp::Le) => same || swapped,
+            (BinOp::Lt, BinOp::Lt | BinOp::Le) | (BinOp::Le, BinOp::Le) => same,
+            (BinOp::Lt, BinOp::Ne) => same || swapped,
+            _ => false,
+        }
+    }
+}
+
+/// How a local is defined, when it is assigned exactly once
+#[derive(Clone)]
+enum Definition<'a, 'tcx> {
+    None,
+    Rvalue(&'a Rvalue<'tcx>),
+    /// destination of a call with the given callee and arguments
+    Call(DefId, &'a [Operand<'tcx>]),
+    Many,
+}
+
+pub struct GuardFacts<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    body: &'a ir::Body<'tcx>,
+    definitions: Vec<Definition<'a, 'tcx>>,
+    /// locals written at most once (arguments on entry) and never borrowed mutably
+    stable: Vec<bool>,
+    /// facts at the entry of each block, `None` for unreachable blocks
+    entry: Vec<Option<Vec<Fact<'tcx>>>>,
+}
+
+impl<'a, 'tcx> GuardFacts<'a, 'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        let mut guards = GuardFacts {
+            tcx,
+            body,
+            definitions: Self::collect_definitions(body),
+            stable: Self::collect_stable(body),
+            entry: vec![None; body.basic_blocks.len()],
+        };
+        guards.compute();
+        guards
+    }
+
+    fn collect_definitions(body: &'a ir::Body<'tcx>) -> Vec<Definition<'a, 'tcx>> {
+        let mut definitions = vec![Definition::None; body.local_decls.len()];
+        let mut define = |place: &mir::Place<'tcx>, definition: Definition<'a, 'tcx>| {
+            let slot = &mut definitions[place.local.index()];
+            *slot = match (slot.clone(), place.projection.is_empty()) {
+                (Definition::None, true) => definition,
+                _ => Definition::Many,
+            };
+        };
+
+        for (_location, statement) in body.statements() {
+            if let StatementKind::Assign(box (lplace, rval)) = &statement.kind {
+                define(lplace, Definition::Rvalue(rval));
+            }
+        }
+        for terminator in body.terminators() {
+            match &terminator.kind {
+                ir::TerminatorKind::StaticCall { args, dest, .. } => match terminator.static_callee() {
+                    Some(callee) => define(dest, Definition::Call(callee, args)),
+                    None => define(dest, Definition::Many),
+                },
+                ir::TerminatorKind::IndirectCall { dest, .. } => define(dest, Definition::Many),
+                _ => {},
+            }
+        }
+        // arguments are defined on entry
+        for definition in definitions[1..=body.original.arg_count].iter_mut() {
+            *definition = Definition::Many;
+        }
+        definitions
+    }
+
+    fn collect_stable(body: &ir::Body<'tcx>) -> Vec<bool> {
+        let mut writes = vec![0usize; body.local_decls.len()];
+        for write in writes[1..=body.original.arg_count].iter_mut() {
+            *write = 1;
+        }
+        let mut borrowed = vec![false; body.local_decls.len()];
+
+        for (_location, statement) in body.statements() {
+            if let StatementKind::Assign(box (lplace, rval)) = &statement.kind {
+                writes[lplace.local.index()] += 1;
+                if let Rvalue::Ref(_, BorrowKind::Mut { .. }, place) | Rvalue::AddressOf(_, place) = rval {
+                    borrowed[place.local.index()] = true;
+                }
+            }
+        }
+        for terminator in body.terminators() {
+            match &terminator.kind {
+                ir::TerminatorKind::StaticCall { dest, .. } | ir::TerminatorKind::IndirectCall { dest, .. } => {
+                    writes[dest.local.index()] += 1;
+                },
+                _ => {},
+            }
+        }
+        writes
+            .into_iter()
+            .zip(borrowed)
+            .map(|(writes, borrowed)| writes <= 1 && !borrowed)
+            .collect()
+    }
+
+    /// The value `operand` was copied from
+    pub fn value(&self, operand: &Operand<'tcx>) -> Value<'tcx> {
+        match operand {
+            Operand::Constant(box constant) => Value::Const(constant.literal),
+            Operand::Copy(place) | Operand::Move(place) => self.place_value(*place),
+        }
+    }
+
+    fn place_value(&self, place: mir::Place<'tcx>) -> Value<'tcx> {
+        let definition = match place.as_local() {
+            Some(local) => &self.definitions[local.index()],
+            None => return Value::Place(place),
+        };
+        // a copy of a local that changes later must not stand for its current value
+        let is_stable = |source: &mir::Place<'tcx>| self.stable[source.local.index()];
+        match definition {
+            Definition::Rvalue(Rvalue::Use(Operand::Constant(box constant))) => Value::Const(constant.literal),
+            Definition::Rvalue(Rvalue::Use(Operand::Copy(source) | Operand::Move(source)))
+            | Definition::Rvalue(Rvalue::CopyForDeref(source))
+                if is_stable(source) =>
+            {
+                self.place_value(*source)
+            },
+            Definition::Rvalue(Rvalue::Len(source)) if is_stable(source) => self.len_of_place(*source),
+            // a reborrow `&*p` points to the same memory as `p`
+            Definition::Rvalue(Rvalue::Ref(_, _, source))
+                if source.projection.len() == 1 && source.is_indirect() && is_stable(source) =>
+            {
+                self.place_value(mir::Place::from(source.local))
+            },
+            Definition::Call(callee, [receiver]) if self.is_len(*callee) => match self.value(receiver) {
+                Value::Place(receiver) => self.len_of_place(receiver),
+                _ => Value::Place(place),
+            },
+            _ => Value::Place(place),
+        }
+    }
+
+    fn is_len(&self, callee: DefId) -> bool {
+        paths::LEN_LIST.contains(&self.tcx.ext().get_def_path(callee))
+    }
+
+    fn len_of_place(&self, place: mir::Place<'tcx>) -> Value<'tcx> {
+        // `Len(*p)` and `len(p)` describe the same slice
+        let place = if place.projection.len() == 1 && place.is_indirect() {
+            mir::Place::from(place.local)
+        } else {
+            place
+        };
+        match self.place_value(place) {
+            Value::Place(root) => Value::Len(root),
+            _ => Value::Len(place),
+        }
+    }
+
+    /// The fact established when the `SwitchInt` on `discr` takes its non-zero branch
+    fn branch_fact(&self, discr: &Operand<'tcx>) -> Option<Fact<'tcx>> {
+        let local = discr.place()?.as_local()?;
+        match &self.definitions[local.index()] {
+            Definition::Rvalue(Rvalue::BinaryOp(op, box (lhs, rhs))) => {
+                Fact::new(self.value(lhs), *op, self.value(rhs))
+            },
+            Definition::Rvalue(Rvalue::UnaryOp(UnOp::Not, operand)) => {
+                self.branch_fact(operand).map(|fact| fact.negate())
+            },
+            _ => None,
+        }
+    }
+
+    fn edge_facts(&self, bb: usize, succ: usize) -> Vec<Fact<'tcx>> {
+        if let ir::TerminatorKind::SwitchInt { discr, targets } = &self.body.basic_blocks[bb].terminator.kind
+            && let Some(fact) = self.branch_fact(discr)
+        {
+            let on_false = targets
+                .iter()
+                .any(|(value, target)| value == 0 && target.index() == succ);
+            let on_true = targets.otherwise().index() == succ
+                || targets.iter().any(|(value, target)| value != 0 && target.index() == succ);
+            return match (on_true, on_false) {
+                (true, false) => vec![fact],
+                (false, true) => vec![fact.negate()],
+                _ => Vec::new(),
+            };
+        }
+        Vec::new()
+    }
+
+    fn kill(&self, facts: &mut Vec<Fact<'tcx>>, statement: &mir::Statement<'tcx>) {
+        if let StatementKind::Assign(box (lplace, rval)) = &statement.kind {
+            facts.retain(|fact| !fact.mentions(lplace.local));
+            match rval {
+                Rvalue::Ref(_, BorrowKind::Mut { .. }, place) | Rvalue::AddressOf(_, place) => {
+                    self.kill_borrowed(facts, place);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Facts about a local that may now be mutated through a pointer, e.g. by `v.push(x)`
+    fn kill_borrowed(&self, facts: &mut Vec<Fact<'tcx>>, place: &mir::Place<'tcx>) {
+        // slices, arrays and `str` keep their length, e.g. `&mut *a` for `copy_from_slice`
+        let fixed_len = matches!(
+            place.ty(&self.body.original, self.tcx).ty.kind(),
+            TyKind::Slice(_) | TyKind::Array(..) | TyKind::Str
+        );
+        if fixed_len {
+            facts.retain(|fact| !fact.mentions_contents(place.local));
+        } else {
+            facts.retain(|fact| !fact.mentions(place.local));
+        }
+    }
+
+    fn exit_facts(&self, bb: usize, entry: &[Fact<'tcx>]) -> Vec<Fact<'tcx>> {
+        let block = &self.body.basic_blocks[bb];
+        let mut facts = entry.to_vec();
+        for statement in &block.statements {
+            self.kill(&mut facts, statement);
+        }
+        match &block.terminator.kind {
+            ir::TerminatorKind::StaticCall { args, dest, .. }
+            | ir::TerminatorKind::IndirectCall { args, dest, .. } => {
+                // the callee may mutate what it receives by value or through a `&mut`
+                for arg in args {
+                    if let Operand::Move(place) = arg {
+                        facts.retain(|fact| !fact.mentions(place.local));
+                    }
+                }
+                facts.retain(|fact| !fact.mentions(dest.local));
+            },
+            _ => {},
+        }
+        facts
+    }
+
+    /// Forward must-analysis: a fact holds at a block if it holds on every incoming edge
+    fn compute(&mut self) {
+        if self.body.basic_blocks.is_empty() {
+            return;
+        }
+        let body = self.body;
+        let mut work_list = VecDeque::new();
+        let mut in_queue = vec![false; body.basic_blocks.len()];
+        self.entry[0] = Some(Vec::new());
+        work_list.push_back(0);
+        in_queue[0] = true;
+
+        while let Some(bb) = work_list.pop_front() {
+            in_queue[bb] = false;
+            let exit = match &self.entry[bb] {
+                Some(entry) => self.exit_facts(bb, entry),
+                None => continue,
+            };
+            for &succ in &body.bb_neighbor_list[bb] {
+                let mut incoming = exit.clone();
+                for fact in self.edge_facts(bb, succ) {
+                    if !incoming.contains(&fact) {
+                        incoming.push(fact);
+                    }
+                }
+                let changed = match &mut self.entry[succ] {
+                    Some(entry) => {
+                        let before = entry.len();
+                        entry.retain(|fact| incoming.contains(fact));
+                        entry.len() != before
+                    },
+                    entry @ None => {
+                        *entry = Some(incoming);
+                        true
+                    },
+                };
+                if changed && !in_queue[succ] {
+                    in_queue[succ] = true;
+                    work_list.push_back(succ);
+                }
+            }
+        }
+    }
+
+    /// Facts that hold right before `location`
+    pub fn facts_at(&self, location: mir::Location) -> Vec<Fact<'tcx>> {
+        let bb = location.block.index();
+        let mut facts = match &self.entry[bb] {
+            Some(entry) => entry.clone(),
+            None => return Vec::new(),
+        };
+        for statement in self.body.basic_blocks[bb].statements.iter().take(location.statement_index) {
+            self.kill(&mut facts, statement);
+        }
+        facts
+    }
+
+    /// Whether `lhs op rhs` is established at `location`, e.g. a subtraction guarded by `lhs >= rhs`
+    pub fn holds(&self, location: mir::Location, lhs: &Operand<'tcx>, op: BinOp, rhs: &Operand<'tcx>) -> bool {
+        match Fact::new(self.value(lhs), op, self.value(rhs)) {
+            Some(query) => self.facts_at(location).iter().any(|fact| fact.implies(&query)),
+            None => false,
+        }
+    }
+
+    /// Whether `a.len() == b.len()` is established at `location`
+    pub fn lengths_equal(&self, location: mir::Location, a: &Operand<'tcx>, b: &Operand<'tcx>) -> bool {
+        let (a, b) = match (self.value(a), self.value(b)) {
+            (Value::Place(a), Value::Place(b)) => (self.len_of_place(a), self.len_of_place(b)),
+            _ => return false,
+        };
+        let query = Fact { lhs: a, op: BinOp::Eq, rhs: b };
+        self.facts_at(location).iter().any(|fact| fact.implies(&query))
+    }
+}
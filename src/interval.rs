@@ -0,0 +1,527 @@
+//! Integer ranges of locals, computed by a forward interval analysis over the basic blocks.
+//! Ranges start from the bounds of the local's type and are narrowed by constants, arithmetic,
+//! `%` and bit masks, `min`/`max`/`clamp` calls and the comparisons `SwitchInt` branches on.
+//! Locals whose address is taken and 128-bit integers are never tracked.
+
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+
+use rustc_middle::mir::{self, BinOp, CastKind, Operand, ProjectionElem, Rvalue, StatementKind, UnOp};
+use rustc_middle::ty::{self, ParamEnv, Ty, TyCtxt};
+use rustc_span::sym;
+
+use crate::ir;
+
+/// Number of times a block entry may grow before its growing ranges are widened to the type bounds
+const WIDEN_AFTER: usize = 3;
+
+/// Closed range `lo..=hi`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub lo: i128,
+    pub hi: i128,
+}
+
+impl Interval {
+    pub fn new(lo: i128, hi: i128) -> Self {
+        Interval { lo, hi }
+    }
+
+    pub fn exact(value: i128) -> Self {
+        Interval { lo: value, hi: value }
+    }
+
+    pub fn is_within(&self, other: &Interval) -> bool {
+        other.lo <= self.lo && self.hi <= other.hi
+    }
+
+    fn hull(&self, other: &Interval) -> Interval {
+        Interval::new(min(self.lo, other.lo), max(self.hi, other.hi))
+    }
+
+    fn is_non_negative(&self) -> bool {
+        self.lo >= 0
+    }
+
+    /// Exact range of `self op other`, if it is known and fits in `i128`
+    fn binary(&self, op: BinOp, other: &Interval) -> Option<Interval> {
+        let corners = |f: fn(i128, i128) -> Option<i128>| -> Option<Interval> {
+            let values = [
+                f(self.lo, other.lo)?,
+                f(self.lo, other.hi)?,
+                f(self.hi, other.lo)?,
+                f(self.hi, other.hi)?,
+            ];
+            Some(Interval::new(*values.iter().min()?, *values.iter().max()?))
+        };
+        match op {
+            BinOp::Add => Some(Interval::new(
+                self.lo.checked_add(other.lo)?,
+                self.hi.checked_add(other.hi)?,
+            )),
+            BinOp::Sub => Some(Interval::new(
+                self.lo.checked_sub(other.hi)?,
+                self.hi.checked_sub(other.lo)?,
+            )),
+            BinOp::Mul => corners(i128::checked_mul),
+            // the quotient is monotonic in both operands as long as the divisor keeps its sign
+            BinOp::Div if other.lo > 0 || other.hi < 0 => corners(i128::checked_div),
+            BinOp::Rem if other.lo > 0 || other.hi < 0 => {
+                let bound = max(other.lo.checked_abs()?, other.hi.checked_abs()?) - 1;
+                if self.is_non_negative() {
+                    Some(Interval::new(0, min(self.hi, bound)))
+                } else {
+                    Some(Interval::new(-bound, bound))
+                }
+            },
+            BinOp::BitAnd => match (self.is_non_negative(), other.is_non_negative()) {
+                (true, true) => Some(Interval::new(0, min(self.hi, other.hi))),
+                (true, false) => Some(Interval::new(0, self.hi)),
+                (false, true) => Some(Interval::new(0, other.hi)),
+                (false, false) => None,
+            },
+            BinOp::BitOr if self.is_non_negative() && other.is_non_negative() => {
+                // all the bits below the highest bit of either operand may be set
+                let highest = max(self.hi, other.hi) as u128;
+                let mask = u128::MAX.checked_shr(highest.leading_zeros()).unwrap_or(0);
+                Some(Interval::new(max(self.lo, other.lo), mask as i128))
+            },
+            BinOp::Shr if self.is_non_negative() && other.is_non_negative() => {
+                let shift = |amount: i128| min(amount, 127) as u32;
+                Some(Interval::new(self.lo >> shift(other.hi), self.hi >> shift(other.lo)))
+            },
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                Some(Interval::new(0, 1))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Values of an integer, `bool` or `char` type, `None` for other types and 128-bit integers
+pub fn type_bounds<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<Interval> {
+    match ty.kind() {
+        ty::Bool => Some(Interval::new(0, 1)),
+        ty::Char => Some(Interval::new(0, char::MAX as i128)),
+        ty::Uint(uint_ty) => {
+            let bits = uint_ty.bit_width().unwrap_or(tcx.data_layout.pointer_size.bits());
+            (bits < 128).then(|| Interval::new(0, (1i128 << bits) - 1))
+        },
+        ty::Int(int_ty) => {
+            let bits = int_ty.bit_width().unwrap_or(tcx.data_layout.pointer_size.bits());
+            (bits < 128).then(|| Interval::new(-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1))
+        },
+        _ => None,
+    }
+}
+
+/// Interprets the raw bits of a constant of type `ty`
+fn value_of_bits<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>, bits: u128) -> Option<i128> {
+    match ty.kind() {
+        ty::Int(int_ty) => {
+            let width = int_ty.bit_width().unwrap_or(tcx.data_layout.pointer_size.bits());
+            let shift = 128 - width as u32;
+            Some(((bits << shift) as i128) >> shift)
+        },
+        _ => i128::try_from(bits).ok(),
+    }
+}
+
+/// Range of every local; `None` means nothing is known beyond the type bounds
+type State = Vec<Option<Interval>>;
+
+fn join_into(entry: &mut State, incoming: &State, widen: bool) -> bool {
+    let mut changed = false;
+    for (current, new) in entry.iter_mut().zip(incoming) {
+        let joined = match (*current, *new) {
+            (Some(current), Some(new)) => {
+                let hull = current.hull(&new);
+                // a range that keeps growing jumps to the type bounds
+                (!widen || hull == current).then_some(hull)
+            },
+            _ => None,
+        };
+        if joined != *current {
+            *current = joined;
+            changed = true;
+        }
+    }
+    changed
+}
+
+pub struct IntervalAnalysis<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    body: &'a ir::Body<'tcx>,
+    /// locals whose address is taken, so they can change behind the analysis' back
+    untracked: Vec<bool>,
+    /// destinations of a `CheckedBinaryOp`, whose range is the one of field 0
+    checked: Vec<bool>,
+    /// number of assignments to each local
+    assignments: Vec<usize>,
+    /// the local a local was copied from, if it is assigned once
+    copy_of: Vec<Option<mir::Local>>,
+    /// the comparison a boolean local holds, if it is assigned once
+    comparisons: Vec<Option<(BinOp, &'a Operand<'tcx>, &'a Operand<'tcx>)>>,
+    /// state at the entry of each block, `None` for unreachable blocks
+    entry: Vec<Option<State>>,
+}
+
+impl<'a, 'tcx> IntervalAnalysis<'a, 'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>, body: &'a ir::Body<'tcx>) -> Self {
+        let local_count = body.local_decls.len();
+        let mut analysis = IntervalAnalysis {
+            tcx,
+            param_env: tcx.param_env(body.original.source.def_id()),
+            body,
+            untracked: vec![false; local_count],
+            checked: vec![false; local_count],
+            assignments: vec![0; local_count],
+            copy_of: vec![None; local_count],
+            comparisons: vec![None; local_count],
+            entry: vec![None; body.basic_blocks.len()],
+        };
+        analysis.collect_definitions();
+        analysis.compute();
+        analysis
+    }
+
+    fn collect_definitions(&mut self) {
+        let body = self.body;
+        for (_location, statement) in body.statements() {
+            let (lplace, rval) = match &statement.kind {
+                StatementKind::Assign(box (lplace, rval)) => (lplace, rval),
+                _ => continue,
+            };
+            let local = lplace.local.index();
+            self.assignments[local] += 1;
+            match rval {
+                Rvalue::Ref(_, _, place) | Rvalue::AddressOf(_, place) => {
+                    self.untracked[place.local.index()] = true;
+                },
+                Rvalue::CheckedBinaryOp(..) => self.checked[local] = true,
+                Rvalue::Use(Operand::Copy(source) | Operand::Move(source)) => {
+                    self.copy_of[local] = source.as_local();
+                },
+                Rvalue::BinaryOp(op, box (lhs, rhs)) => self.comparisons[local] = Some((*op, lhs, rhs)),
+                _ => {},
+            }
+        }
+        for terminator in body.terminators() {
+            if let ir::TerminatorKind::StaticCall { dest, .. } | ir::TerminatorKind::IndirectCall { dest, .. } =
+                &terminator.kind
+            {
+                self.assignments[dest.local.index()] += 1;
+            }
+        }
+        for local in 0..self.assignments.len() {
+            if self.assignments[local] != 1 {
+                self.copy_of[local] = None;
+                self.comparisons[local] = None;
+            }
+        }
+    }
+
+    fn local_bounds(&self, local: mir::Local) -> Option<Interval> {
+        type_bounds(self.tcx, self.body.local_decls[local.index()].ty)
+    }
+
+    fn read_local(&self, state: &State, local: mir::Local) -> Option<Interval> {
+        if self.untracked[local.index()] {
+            return self.local_bounds(local);
+        }
+        state[local.index()].or_else(|| self.local_bounds(local))
+    }
+
+    fn eval_place(&self, state: &State, place: &mir::Place<'tcx>) -> Option<Interval> {
+        match place.as_ref().projection {
+            [] => self.read_local(state, place.local),
+            [ProjectionElem::Field(field, _)] if self.checked[place.local.index()] && field.index() == 0 => {
+                self.read_local(state, place.local)
+            },
+            _ => type_bounds(self.tcx, place.ty(self.body, self.tcx).ty),
+        }
+    }
+
+    fn eval_operand(&self, state: &State, operand: &Operand<'tcx>) -> Option<Interval> {
+        match operand {
+            Operand::Copy(place) | Operand::Move(place) => self.eval_place(state, place),
+            Operand::Constant(box constant) => {
+                let ty = constant.ty();
+                constant
+                    .literal
+                    .try_eval_bits(self.tcx, self.param_env, ty)
+                    .and_then(|bits| value_of_bits(self.tcx, ty, bits))
+                    .map(Interval::exact)
+                    .or_else(|| type_bounds(self.tcx, ty))
+            },
+        }
+    }
+
+    /// Range of `lhs op rhs` in the type of `lhs`, or `None` if it may leave the type bounds
+    fn eval_binary(
+        &self,
+        state: &State,
+        op: BinOp,
+        lhs: &Operand<'tcx>,
+        rhs: &Operand<'tcx>,
+    ) -> Option<Interval> {
+        let (lhs_range, rhs_range) = (self.eval_operand(state, lhs)?, self.eval_operand(state, rhs)?);
+        let result = lhs_range.binary(op, &rhs_range)?;
+        match op {
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => Some(result),
+            _ => {
+                let bounds = type_bounds(self.tcx, lhs.ty(self.body, self.tcx))?;
+                // `MIN / -1` and `MIN % -1` overflow, although the remainder itself would be 0
+                let min_by_minus_one = lhs_range.lo <= bounds.lo && rhs_range.lo <= -1 && -1 <= rhs_range.hi;
+                if matches!(op, BinOp::Div | BinOp::Rem) && min_by_minus_one {
+                    return None;
+                }
+                result.is_within(&bounds).then_some(result)
+            },
+        }
+    }
+
+    fn eval_rvalue(&self, state: &State, rval: &Rvalue<'tcx>) -> Option<Interval> {
+        match rval {
+            Rvalue::Use(operand) => self.eval_operand(state, operand),
+            Rvalue::Cast(CastKind::IntToInt, operand, to_ty) => {
+                let bounds = type_bounds(self.tcx, *to_ty)?;
+                match self.eval_operand(state, operand) {
+                    Some(range) if range.is_within(&bounds) => Some(range),
+                    _ => Some(bounds),
+                }
+            },
+            Rvalue::BinaryOp(op, box (lhs, rhs)) | Rvalue::CheckedBinaryOp(op, box (lhs, rhs)) => {
+                self.eval_binary(state, *op, lhs, rhs)
+            },
+            Rvalue::UnaryOp(UnOp::Neg, operand) => {
+                let range = self.eval_operand(state, operand)?;
+                let negated = Interval::new(range.hi.checked_neg()?, range.lo.checked_neg()?);
+                negated
+                    .is_within(&type_bounds(self.tcx, operand.ty(self.body, self.tcx))?)
+                    .then_some(negated)
+            },
+            _ => None,
+        }
+    }
+
+    /// Range of the value returned by `Ord::min`, `Ord::max` or `Ord::clamp` on integers
+    fn eval_call(&self, state: &State, terminator: &ir::Terminator<'tcx>) -> Option<Interval> {
+        let (callee_did, args) = match &terminator.kind {
+            ir::TerminatorKind::StaticCall { callee_did, args, .. } => (*callee_did, args),
+            _ => return None,
+        };
+        let trait_did = self.tcx.trait_of_item(callee_did)?;
+        if !self.tcx.is_diagnostic_item(sym::Ord, trait_did) {
+            return None;
+        }
+        let ranges: Option<Vec<Interval>> = args.iter().map(|arg| self.eval_operand(state, arg)).collect();
+        match (self.tcx.item_name(callee_did).as_str(), &ranges?[..]) {
+            ("min", [a, b]) => Some(Interval::new(min(a.lo, b.lo), min(a.hi, b.hi))),
+            ("max", [a, b]) => Some(Interval::new(max(a.lo, b.lo), max(a.hi, b.hi))),
+            // `clamp(x, lo, hi)` is `max(min(x, hi), lo)`
+            ("clamp", [x, lo, hi]) => {
+                let upper = Interval::new(min(x.lo, hi.lo), min(x.hi, hi.hi));
+                Some(Interval::new(max(upper.lo, lo.lo), max(upper.hi, lo.hi)))
+            },
+            _ => None,
+        }
+    }
+
+    fn assign(&self, state: &mut State, place: &mir::Place<'tcx>, range: Option<Interval>) {
+        // a partial write leaves nothing known about the local
+        state[place.local.index()] = if place.projection.is_empty() { range } else { None };
+    }
+
+    fn apply_statement(&self, state: &mut State, statement: &mir::Statement<'tcx>) {
+        if let StatementKind::Assign(box (lplace, rval)) = &statement.kind {
+            let range = self.eval_rvalue(state, rval);
+            self.assign(state, lplace, range);
+        }
+    }
+
+    fn exit_state(&self, bb: usize, entry: &State) -> State {
+        let block = &self.body.basic_blocks[bb];
+        let mut state = entry.clone();
+        for statement in &block.statements {
+            self.apply_statement(&mut state, statement);
+        }
+        match &block.terminator.kind {
+            ir::TerminatorKind::StaticCall { dest, .. } | ir::TerminatorKind::IndirectCall { dest, .. } => {
+                let range = self.eval_call(&state, &block.terminator);
+                self.assign(&mut state, dest, range);
+            },
+            _ => {},
+        }
+        state
+    }
+
+    /// Narrows the range of `operand`, and of the local it was copied from, to `range`
+    fn refine(&self, state: &mut State, operand: &Operand<'tcx>, range: Interval) {
+        let mut local = match operand.place().and_then(|place| place.as_local()) {
+            Some(local) => local,
+            None => return,
+        };
+        loop {
+            if !self.untracked[local.index()] && let Some(current) = self.read_local(state, local) {
+                let narrowed = Interval::new(max(current.lo, range.lo), min(current.hi, range.hi));
+                // an empty range means the edge is infeasible, which is not tracked
+                if narrowed.lo <= narrowed.hi {
+                    state[local.index()] = Some(narrowed);
+                }
+            }
+            match self.copy_of[local.index()] {
+                Some(source) if self.assignments[source.index()] <= 1 => local = source,
+                _ => break,
+            }
+        }
+    }
+
+    /// Narrows `state` with the comparison `lhs op rhs`, known to be `holds`
+    fn refine_comparison(
+        &self,
+        state: &mut State,
+        op: BinOp,
+        lhs: &Operand<'tcx>,
+        rhs: &Operand<'tcx>,
+        holds: bool,
+    ) {
+        let (op, lhs, rhs) = match (op, holds) {
+            (BinOp::Lt, true) | (BinOp::Ge, false) => (BinOp::Lt, lhs, rhs),
+            (BinOp::Le, true) | (BinOp::Gt, false) => (BinOp::Le, lhs, rhs),
+            (BinOp::Gt, true) | (BinOp::Le, false) => (BinOp::Lt, rhs, lhs),
+            (BinOp::Ge, true) | (BinOp::Lt, false) => (BinOp::Le, rhs, lhs),
+            (BinOp::Eq, true) | (BinOp::Ne, false) => (BinOp::Eq, lhs, rhs),
+            _ => return,
+        };
+        let (lhs_range, rhs_range) = match (self.eval_operand(state, lhs), self.eval_operand(state, rhs)) {
+            (Some(lhs_range), Some(rhs_range)) => (lhs_range, rhs_range),
+            _ => return,
+        };
+        let strict = (op == BinOp::Lt) as i128;
+        match op {
+            BinOp::Eq => {
+                self.refine(state, lhs, rhs_range);
+                self.refine(state, rhs, lhs_range);
+            },
+            _ => {
+                // lhs <= rhs - strict
+                self.refine(state, lhs, Interval::new(i128::MIN, rhs_range.hi - strict));
+                self.refine(state, rhs, Interval::new(lhs_range.lo + strict, i128::MAX));
+            },
+        }
+    }
+
+    fn edge_state(&self, bb: usize, succ: usize, exit: &State) -> State {
+        let mut state = exit.clone();
+        let (discr, targets) = match &self.body.basic_blocks[bb].terminator.kind {
+            ir::TerminatorKind::SwitchInt { discr, targets } => (discr, targets),
+            _ => return state,
+        };
+        let values: Vec<u128> = targets
+            .iter()
+            .filter(|&(_, target)| target.index() == succ)
+            .map(|(value, _)| value)
+            .collect();
+        let otherwise = targets.otherwise().index() == succ;
+
+        let comparison = discr
+            .place()
+            .and_then(|place| place.as_local())
+            .and_then(|local| self.comparisons[local.index()]);
+        if let Some((op, lhs, rhs)) = comparison {
+            // `false` is 0, and `true` is the only other value
+            match (values.as_slice(), otherwise) {
+                ([0], false) => self.refine_comparison(&mut state, op, lhs, rhs, false),
+                ([], true) | ([1], false) => self.refine_comparison(&mut state, op, lhs, rhs, true),
+                _ => {},
+            }
+        } else if !otherwise && !values.is_empty() {
+            let ty = discr.ty(self.body, self.tcx);
+            let ranges: Option<Vec<i128>> = values
+                .iter()
+                .map(|&bits| value_of_bits(self.tcx, ty, bits))
+                .collect();
+            if let Some(ranges) = ranges {
+                let range = Interval::new(*ranges.iter().min().unwrap(), *ranges.iter().max().unwrap());
+                self.refine(&mut state, discr, range);
+            }
+        }
+        state
+    }
+
+    fn compute(&mut self) {
+        if self.body.basic_blocks.is_empty() {
+            return;
+        }
+        let body = self.body;
+        let mut visits = vec![0; body.basic_blocks.len()];
+        let mut work_list = VecDeque::new();
+        let mut in_queue = vec![false; body.basic_blocks.len()];
+        self.entry[0] = Some(vec![None; body.local_decls.len()]);
+        work_list.push_back(0);
+        in_queue[0] = true;
+
+        while let Some(bb) = work_list.pop_front() {
+            in_queue[bb] = false;
+            let exit = match &self.entry[bb] {
+                Some(entry) => self.exit_state(bb, entry),
+                None => continue,
+            };
+            for &succ in &body.bb_neighbor_list[bb] {
+                let incoming = self.edge_state(bb, succ, &exit);
+                let changed = match &mut self.entry[succ] {
+                    Some(entry) => {
+                        visits[succ] += 1;
+                        join_into(entry, &incoming, visits[succ] > WIDEN_AFTER)
+                    },
+                    entry @ None => {
+                        *entry = Some(incoming);
+                        true
+                    },
+                };
+                if changed && !in_queue[succ] {
+                    in_queue[succ] = true;
+                    work_list.push_back(succ);
+                }
+            }
+        }
+    }
+
+    fn state_at(&self, location: mir::Location) -> Option<State> {
+        let bb = location.block.index();
+        let mut state = self.entry[bb].clone()?;
+        for statement in self.body.basic_blocks[bb].statements.iter().take(location.statement_index) {
+            self.apply_statement(&mut state, statement);
+        }
+        Some(state)
+    }
+
+    /// Range of `operand` right before `location`
+    pub fn range(&self, location: mir::Location, operand: &Operand<'tcx>) -> Option<Interval> {
+        self.eval_operand(&self.state_at(location)?, operand)
+    }
+
+    /// Whether `operand` provably fits in `ty` at `location`, e.g. `x & 0xff` in `u8`
+    pub fn fits(&self, location: mir::Location, operand: &Operand<'tcx>, ty: Ty<'tcx>) -> bool {
+        match (self.range(location, operand), type_bounds(self.tcx, ty)) {
+            (Some(range), Some(bounds)) => range.is_within(&bounds),
+            _ => false,
+        }
+    }
+
+    /// Whether `lhs op rhs` at `location` provably stays within the type of `lhs`
+    pub fn cannot_overflow(
+        &self,
+        location: mir::Location,
+        op: BinOp,
+        lhs: &Operand<'tcx>,
+        rhs: &Operand<'tcx>,
+    ) -> bool {
+        match self.state_at(location) {
+            Some(state) => self.eval_binary(&state, op, lhs, rhs).is_some(),
+            None => false,
+        }
+    }
+}
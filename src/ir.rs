@@ -2,6 +2,7 @@
 //! Note that this is a translation of non-monomorphized, generic MIR.
 
 use std::borrow::Cow;
+use std::cell::OnceCell;
 use std::collections::{VecDeque, HashMap};
 use rustc_hir::def_id::DefId;
 use rustc_index::{IndexVec, IndexSlice};
@@ -9,7 +10,25 @@ use rustc_middle::{
     mir,
     ty::{subst::SubstsRef, Ty},
 };
-use crate::progress_info;
+use crate::dominators::{self, DominatorTree, NaturalLoop};
+
+/// A (possibly resolved) call target
+#[derive(Debug, Clone, Copy)]
+pub struct Callee<'tcx> {
+    pub did: DefId,
+    pub substs: SubstsRef<'tcx>,
+}
+
+/// How the target of an `IndirectCall` is chosen at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// Call through a `fn` pointer
+    FnPtr,
+    /// `Fn::call`, `FnMut::call_mut` or `FnOnce::call_once`
+    Closure,
+    /// Method call on a `dyn Trait` object
+    Virtual,
+}
 
 #[derive(Debug)]
 pub struct Terminator<'tcx> {
@@ -17,7 +36,23 @@ pub struct Terminator<'tcx> {
     pub original: mir::Terminator<'tcx>,
 }
 
+impl<'tcx> Terminator<'tcx> {
+    /// Target of a `StaticCall`, preferring the impl item it resolves to
+    pub fn static_callee(&self) -> Option<DefId> {
+        match &self.kind {
+            TerminatorKind::StaticCall {
+                callee_did,
+                resolved,
+                ..
+            } => Some(resolved.map_or(*callee_did, |callee| callee.did)),
+            _ => None,
+        }
+    }
+}
+
 // https://doc.rust-lang.org/stable/nightly-rustc/rustc_middle/mir/syntax/enum.TerminatorKind.html
+// Successors are basic block indices.
+// `unwind`/`drop` are `None` when the terminator has no cleanup block to jump to.
 #[derive(Debug)]
 pub enum TerminatorKind<'tcx> {
     Goto(usize),
@@ -27,18 +62,223 @@ pub enum TerminatorKind<'tcx> {
         callee_substs: SubstsRef<'tcx>,
         args: Vec<mir::Operand<'tcx>>,
         dest: mir::Place<'tcx>,
+        /// The impl item selected by `Instance::resolve` when `callee_did` is a trait method
+        resolved: Option<Callee<'tcx>>,
+    },
+    /// Calls whose target isn't known syntactically.
+    /// `callees` are the candidate targets, which may be empty if nothing could be resolved.
+    IndirectCall {
+        kind: CallKind,
+        callees: Vec<Callee<'tcx>>,
+        args: Vec<mir::Operand<'tcx>>,
+        dest: mir::Place<'tcx>,
     },
     SwitchInt {
         discr: mir::Operand<'tcx>,
         targets: mir::SwitchTargets,
     },
-    FnPtr {
-        value: mir::ConstantKind<'tcx>,
+    Drop {
+        place: mir::Place<'tcx>,
+        target: usize,
+        unwind: Option<usize>,
+    },
+    /// Runtime checks inserted by rustc, e.g. overflow and bounds checks.
+    /// Panics with `msg` unless `cond == expected`.
+    Assert {
+        cond: mir::Operand<'tcx>,
+        expected: bool,
+        msg: mir::AssertMessage<'tcx>,
+        target: usize,
+        unwind: Option<usize>,
+    },
+    /// Suspension point of a generator (and thus of an async fn)
+    Yield {
+        value: mir::Operand<'tcx>,
+        resume: usize,
+        resume_arg: mir::Place<'tcx>,
+        drop: Option<usize>,
+    },
+    GeneratorDrop,
+    /// Continues unwinding; ends every cleanup path
+    UnwindResume,
+    /// Aborts the unwinding (e.g. a panic while panicking)
+    UnwindTerminate,
+    Unreachable,
+    InlineAsm {
+        destination: Option<usize>,
+        unwind: Option<usize>,
+    },
+    /// Loop header inserted by MIR building; behaves as a `Goto(real_target)`
+    FalseUnwind {
+        real_target: usize,
+        unwind: Option<usize>,
     },
-    Drop(usize),
     Unimplemented(Cow<'static, str>),
 }
 
+/// Maximum number of projections kept in a place node; deeper places are folded into their prefix
+pub const PLACE_DEPTH_LIMIT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlaceStep {
+    Deref,
+    Field(usize),
+    /// Any element of an array or slice, i.e. `[i]`, constant indices and subslices
+    Index,
+    Downcast(usize),
+}
+
+/// A local together with a bounded projection path, e.g. `(*_3).0` is `(3, [Deref, Field(0)])`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlaceNode {
+    pub local: usize,
+    pub path: Vec<PlaceStep>,
+}
+
+impl PlaceNode {
+    pub fn from_place(place: &mir::Place<'_>) -> Self {
+        let mut path = Vec::new();
+        for elem in place.projection.iter() {
+            if path.len() == PLACE_DEPTH_LIMIT {
+                break;
+            }
+            let step = match elem {
+                mir::ProjectionElem::Deref => PlaceStep::Deref,
+                mir::ProjectionElem::Field(field, _) => PlaceStep::Field(field.index()),
+                mir::ProjectionElem::Index(_)
+                | mir::ProjectionElem::ConstantIndex { .. }
+                | mir::ProjectionElem::Subslice { .. } => PlaceStep::Index,
+                mir::ProjectionElem::Downcast(_, variant) => PlaceStep::Downcast(variant.index()),
+                mir::ProjectionElem::OpaqueCast(_) => continue,
+            };
+            path.push(step);
+        }
+        PlaceNode {
+            local: place.local.index(),
+            path,
+        }
+    }
+
+    /// Whether `place` has more projections than `PLACE_DEPTH_LIMIT` keeps
+    pub fn is_folded(place: &mir::Place<'_>) -> bool {
+        let depth = place
+            .projection
+            .iter()
+            .filter(|elem| !matches!(elem, mir::ProjectionElem::OpaqueCast(_)))
+            .count();
+        depth > PLACE_DEPTH_LIMIT
+    }
+
+    /// Whether `other` is `self` or one of its projections
+    pub fn is_prefix_of(&self, other: &PlaceNode) -> bool {
+        self.local == other.local && other.path.starts_with(&self.path)
+    }
+}
+
+/// Numbering of the place nodes of a body.
+/// Node `i` for `i < local count` is always the whole local `_i`, so `local.index()` stays a valid node id.
+#[derive(Debug)]
+pub struct PlaceIndex {
+    nodes: Vec<PlaceNode>,
+    ids: HashMap<PlaceNode, usize>,
+    nodes_of_local: Vec<Vec<usize>>,
+}
+
+impl PlaceIndex {
+    pub fn new(local_count: usize) -> Self {
+        let mut index = PlaceIndex {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            nodes_of_local: vec![Vec::new(); local_count],
+        };
+        for local in 0..local_count {
+            index.insert(PlaceNode {
+                local,
+                path: Vec::new(),
+            });
+        }
+        index
+    }
+
+    fn insert(&mut self, node: PlaceNode) -> usize {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = self.nodes.len();
+        self.nodes_of_local[node.local].push(id);
+        self.ids.insert(node.clone(), id);
+        self.nodes.push(node);
+        id
+    }
+
+    pub fn intern(&mut self, place: &mir::Place<'_>) -> usize {
+        self.insert(PlaceNode::from_place(place))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn node(&self, id: usize) -> &PlaceNode {
+        &self.nodes[id]
+    }
+
+    /// Node of `place`, or of its longest interned prefix
+    pub fn get(&self, place: &mir::Place<'_>) -> usize {
+        let mut node = PlaceNode::from_place(place);
+        loop {
+            if let Some(&id) = self.ids.get(&node) {
+                return id;
+            }
+            node.path.pop();
+        }
+    }
+
+    /// `id` and every interned projection of it
+    pub fn projections_of(&self, id: usize) -> Vec<usize> {
+        let base = &self.nodes[id];
+        self.nodes_of_local[base.local]
+            .iter()
+            .copied()
+            .filter(|&other| base.is_prefix_of(&self.nodes[other]))
+            .collect()
+    }
+
+    /// Nodes whose value is observed when `place` is read: the place itself,
+    /// its prefixes and its projections, but not its siblings.
+    pub fn read_nodes(&self, place: &mir::Place<'_>) -> Vec<usize> {
+        let target = self.nodes[self.get(place)].clone();
+        self.nodes_of_local[target.local]
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let node = &self.nodes[id];
+                node.is_prefix_of(&target) || target.is_prefix_of(node)
+            })
+            .collect()
+    }
+}
+
+/// Places whose value flows into the result of `rval`
+pub fn rvalue_reads<'tcx>(rval: &mir::Rvalue<'tcx>) -> Vec<mir::Place<'tcx>> {
+    match rval {
+        mir::Rvalue::Cast(_, op, _)
+        | mir::Rvalue::Use(op)
+        | mir::Rvalue::Repeat(op, _)
+        | mir::Rvalue::ShallowInitBox(op, _) => op.place().into_iter().collect(),
+        mir::Rvalue::Ref(_, _, place)
+        | mir::Rvalue::AddressOf(_, place)
+        | mir::Rvalue::Len(place)
+        | mir::Rvalue::Discriminant(place)
+        | mir::Rvalue::CopyForDeref(place) => vec![*place],
+        mir::Rvalue::BinaryOp(_, box (op1, op2))
+        | mir::Rvalue::CheckedBinaryOp(_, box (op1, op2)) => {
+            [op1.place(), op2.place()].into_iter().flatten().collect()
+        },
+        _ => Vec::new(),
+    }
+}
+
 #[derive(Debug)]
 pub struct BasicBlock<'tcx> {
     pub statements: Vec<mir::Statement<'tcx>>,
@@ -57,8 +297,19 @@ pub struct Body<'tcx> {
     pub original_decls: IndexVec<mir::Local, mir::LocalDecl<'tcx>>,
     pub basic_blocks: Vec<BasicBlock<'tcx>>,
     pub original: mir::Body<'tcx>,
+    pub place_index: PlaceIndex,
+    /// Dataflow edges between the nodes of `place_index`
     pub place_neighbor_list: Vec<Vec<usize>>,
+    /// Reverse of `place_neighbor_list`
+    pub place_predecessor_list: Vec<Vec<usize>>,
     pub bb_neighbor_list: Vec<Vec<usize>>,
+    /// Source line of every statement of a basic block, followed by the line of its terminator
+    pub source_lines: Vec<Vec<usize>>,
+    /// Computed on first use, see `dominators()` and `post_dominators()`
+    pub dominators: OnceCell<DominatorTree>,
+    pub post_dominators: OnceCell<DominatorTree>,
+    /// Whether each block can reach a panic block, see `always_returns()`
+    pub may_panic: OnceCell<Vec<bool>>,
 }
 
 impl<'tcx> mir::HasLocalDecls<'tcx> for Body<'tcx> {
@@ -68,49 +319,197 @@ impl<'tcx> mir::HasLocalDecls<'tcx> for Body<'tcx> {
 }
 
 impl<'tcx> Body<'tcx> {
-    pub fn statements(&self) -> Vec<mir::Statement<'tcx>> {
-        let mut statement_list: Vec<mir::Statement<'tcx>> = Vec::new();
-        for block in &self.basic_blocks {
-            for st in &block.statements {
-                statement_list.push(st.clone());
+    /// Statements in basic block order, with their location in the CFG
+    pub fn statements(&self) -> impl Iterator<Item = (mir::Location, &mir::Statement<'tcx>)> + '_ {
+        self.basic_blocks.iter().enumerate().flat_map(|(bb, block)| {
+            block.statements.iter().enumerate().map(move |(statement_index, statement)| {
+                let location = mir::Location {
+                    block: mir::BasicBlock::from_usize(bb),
+                    statement_index,
+                };
+                (location, statement)
+            })
+        })
+    }
+
+    /// Source line of the statement at `location`, or of the terminator if `location` is
+    /// one past the last statement (as in `mir::Body::terminator_loc`)
+    pub fn line_of(&self, location: mir::Location) -> usize {
+        self.source_lines[location.block.index()][location.statement_index]
+    }
+
+    pub fn source_info(&self, location: mir::Location) -> mir::SourceInfo {
+        let block = &self.basic_blocks[location.block.index()];
+        match block.statements.get(location.statement_index) {
+            Some(statement) => statement.source_info,
+            None => block.terminator.original.source_info,
+        }
+    }
+
+    /// Statements and call terminators whose written place can flow into the place node `id`,
+    /// in location order. Like the place graph itself, this ignores control dependencies.
+    pub fn backward_slice(&self, id: usize) -> Vec<mir::Location> {
+        let mut reaching = vec![false; self.place_index.len()];
+        let mut work_list = VecDeque::new();
+        reaching[id] = true;
+        work_list.push_back(id);
+        while let Some(curr) = work_list.pop_front() {
+            for &prev in &self.place_predecessor_list[curr] {
+                if !reaching[prev] {
+                    reaching[prev] = true;
+                    work_list.push_back(prev);
+                }
             }
         }
-        statement_list
+
+        let mut slice = Vec::new();
+        for (bb, block) in self.basic_blocks.iter().enumerate() {
+            let block_index = mir::BasicBlock::from_usize(bb);
+            for (statement_index, statement) in block.statements.iter().enumerate() {
+                if let mir::StatementKind::Assign(box (lplace, _)) = &statement.kind
+                    && reaching[self.place_node(lplace)]
+                {
+                    slice.push(mir::Location { block: block_index, statement_index });
+                }
+            }
+            if let TerminatorKind::StaticCall { dest, .. } | TerminatorKind::IndirectCall { dest, .. } =
+                &block.terminator.kind
+                && reaching[self.place_node(dest)]
+            {
+                slice.push(mir::Location { block: block_index, statement_index: block.statements.len() });
+            }
+        }
+        slice
+    }
+
+    pub fn terminator_loc(&self, bb: mir::BasicBlock) -> mir::Location {
+        mir::Location {
+            block: bb,
+            statement_index: self.basic_blocks[bb.index()].statements.len(),
+        }
+    }
+
+    pub fn terminator_line(&self, bb: mir::BasicBlock) -> usize {
+        *self.source_lines[bb.index()]
+            .last()
+            .expect("every basic block has a terminator")
+    }
+
+    /// Taint node written by an assignment to `place`
+    pub fn place_node(&self, place: &mir::Place<'tcx>) -> usize {
+        self.place_index.get(place)
+    }
+
+    /// Taint nodes that a read of `place` depends on
+    pub fn read_nodes(&self, place: &mir::Place<'tcx>) -> Vec<usize> {
+        self.place_index.read_nodes(place)
     }
 
     pub fn terminators(&self) -> impl Iterator<Item = &Terminator<'tcx>> {
         self.basic_blocks.iter().map(|block| &block.terminator)
     }
 
-    pub fn arr_return(&self, idx: usize, ret_idx: usize) -> Option<Vec<usize>> {
-        // check whether the basic block with idx can navigate to return in two-level depth
-        let mut work_list = VecDeque::new();
-        let mut visited = HashMap::new();
+    pub fn located_terminators(&self) -> impl Iterator<Item = (mir::BasicBlock, &Terminator<'tcx>)> + '_ {
+        self.basic_blocks
+            .iter()
+            .enumerate()
+            .map(|(bb, block)| (mir::BasicBlock::from_usize(bb), &block.terminator))
+    }
 
-        work_list.push_back(idx);
-        visited.insert(idx, None);
-        while let Some(curr) = work_list.pop_front() {
-            if curr == ret_idx {
-                let mut path = Vec::new();
-                let mut step = Some(curr);
-                while let Some(node) = step {
-                    path.push(node);
-                    step = visited[&node];
-                }
-                path.reverse();
-                return Some(path);
-            }
+    /// Successors of `bb` that don't start unwinding
+    pub fn normal_successors(&self, bb: usize) -> Vec<usize> {
+        let is_cleanup = self.basic_blocks[bb].is_cleanup;
+        self.bb_neighbor_list[bb]
+            .iter()
+            .copied()
+            .filter(|&succ| self.basic_blocks[succ].is_cleanup == is_cleanup)
+            .collect()
+    }
+
+    fn normal_successor_list(&self) -> Vec<Vec<usize>> {
+        (0..self.basic_blocks.len())
+            .map(|bb| self.normal_successors(bb))
+            .collect()
+    }
 
-            progress_info!("curr: {:?} 's neighbors: {:?}", curr, &self.bb_neighbor_list[curr]);
-            for next in &self.bb_neighbor_list[curr] {
-                if !visited.contains_key(next) {
-                    work_list.push_back(*next);
-                    visited.insert(*next, Some(curr));
+    /// Dominator tree of the blocks reachable from the entry without unwinding
+    pub fn dominators(&self) -> &DominatorTree {
+        self.dominators
+            .get_or_init(|| DominatorTree::compute(&self.normal_successor_list(), 0))
+    }
+
+    /// Post-dominator tree over the edges that don't unwind. Its root is the virtual exit
+    /// `basic_blocks.len()`, which follows `Return` and every call that diverges.
+    pub fn post_dominators(&self) -> &DominatorTree {
+        self.post_dominators
+            .get_or_init(|| dominators::post_dominators(&self.normal_successor_list()))
+    }
+
+    /// Block where all the paths leaving `bb` join again, if they join before the exit
+    pub fn immediate_post_dominator(&self, bb: usize) -> Option<usize> {
+        self.post_dominators()
+            .immediate_dominator(bb)
+            .filter(|&pdom| pdom != self.basic_blocks.len())
+    }
+
+    pub fn natural_loops(&self) -> Vec<NaturalLoop> {
+        dominators::natural_loops(&self.normal_successor_list(), self.dominators())
+    }
+
+    /// Whether `bb` ends the normal control flow with a panic: an abort, an unwind, an
+    /// `Unreachable`, or a call to a diverging function like `panic!` or `Option::unwrap_failed`.
+    /// Other exits, e.g. `GeneratorDrop` of a generator or async body, aren't panics.
+    pub fn is_panic_block(&self, bb: usize) -> bool {
+        match self.basic_blocks[bb].terminator.kind {
+            TerminatorKind::UnwindResume | TerminatorKind::UnwindTerminate | TerminatorKind::Unreachable => true,
+            TerminatorKind::StaticCall { .. } | TerminatorKind::IndirectCall { .. } => {
+                self.normal_successors(bb).is_empty()
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether every path from `bb` reaches a `Return` without passing a panic.
+    /// Unwind edges are ignored, and so are paths that loop forever.
+    pub fn always_returns(&self, bb: usize) -> bool {
+        !self.may_panic.get_or_init(|| self.blocks_reaching_panic())[bb]
+    }
+
+    fn blocks_reaching_panic(&self) -> Vec<bool> {
+        let mut may_panic = vec![false; self.basic_blocks.len()];
+        let mut predecessors = vec![Vec::new(); self.basic_blocks.len()];
+        let mut work_list = VecDeque::new();
+        for from in 0..self.basic_blocks.len() {
+            for to in self.normal_successors(from) {
+                predecessors[to].push(from);
+            }
+            if self.is_panic_block(from) {
+                may_panic[from] = true;
+                work_list.push_back(from);
+            }
+        }
+        while let Some(curr) = work_list.pop_front() {
+            for &prev in &predecessors[curr] {
+                if !may_panic[prev] {
+                    may_panic[prev] = true;
+                    work_list.push_back(prev);
                 }
             }
         }
+        may_panic
+    }
 
-        None
+    /// Whether `bb` only drops locals on its way to a `Return`
+    pub fn is_epilogue(&self, bb: usize) -> bool {
+        let mut curr = bb;
+        for _ in 0..self.basic_blocks.len() {
+            curr = match self.basic_blocks[curr].terminator.kind {
+                TerminatorKind::Return => return true,
+                TerminatorKind::Goto(target) | TerminatorKind::Drop { target, .. } => target,
+                _ => return false,
+            };
+        }
+        false
     }
 
     pub fn is_direct_successor(&self, pre: usize, suc: usize) -> bool {
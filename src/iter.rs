@@ -4,6 +4,7 @@
 //! detail here.
 
 use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_middle::ty::TyCtxt;
 use crate::context::RuMorphCtxt;
 
 use crate::prelude::*;
@@ -15,8 +16,13 @@ pub struct LocalTraitIter {
 }
 
 impl LocalTraitIter {
-    pub fn new<'tcx>(rcx: RuMorphCtxt<'tcx>, trait_def_id: DefId) -> Self {
-        let local_trait_impl_map = rcx.tcx().all_local_trait_impls(());
+    pub fn new<'tcx>(rcx: RuMorphCtxt<'_, 'tcx>, trait_def_id: DefId) -> Self {
+        Self::from_tcx(rcx.tcx(), trait_def_id)
+    }
+
+    /// Same as `new`, usable before `RuMorphCtxt` is available (e.g. during translation).
+    pub fn from_tcx<'tcx>(tcx: TyCtxt<'tcx>, trait_def_id: DefId) -> Self {
+        let local_trait_impl_map = tcx.all_local_trait_impls(());
         let impl_id_vec = local_trait_impl_map
             .get(&trait_def_id)
             .map(Clone::clone)
@@ -12,7 +12,9 @@ extern crate rustc_hir_pretty;
 extern crate rustc_index;
 extern crate rustc_interface;
 extern crate rustc_middle;
+extern crate rustc_session;
 extern crate rustc_span;
+extern crate rustc_target;
 
 #[macro_use]
 extern crate bitflags;
@@ -30,16 +32,33 @@ mod macros;
 mod analysis;
 pub mod log;
 pub mod report;
+pub mod diff;
 pub mod utils;
 pub mod context;
 pub mod visitor;
 pub mod ir;
 pub mod graph;
+pub mod dominators;
+pub mod callgraph;
+pub mod dataflow;
+pub mod provenance;
+pub mod guard;
+pub mod interval;
+pub mod target;
+pub mod marker;
+pub mod validity;
+pub mod dump;
 pub mod iter;
 pub mod paths;
+pub mod resolve;
+pub mod summary;
 pub mod prelude;
 
+use std::path::PathBuf;
+use std::sync::Arc;
+
 use rustc_middle::ty::TyCtxt;
+use rustc_target::spec::TargetTriple;
 
 use crate::analysis::{BrokenLayoutChecker, 
     UninitExposureChecker, 
@@ -47,18 +66,20 @@ use crate::analysis::{BrokenLayoutChecker,
     UnsafeDataflowChecker, 
     OverflowChecker, 
     ErrHandleChecker,
-    TruncationChecker
+    TruncationChecker,
+    MonoLayoutChecker,
 };
 use crate::log::Verbosity;
-use crate::report::ReportLevel;
+use crate::report::{Findings, ReportLevel, ReportLogger};
 use crate::context::RuMorphCtxtOwner;
+use crate::marker::MarkerTraits;
 
 // Insert rustc arguments at the beginning of the argument list that RuMorph wants to be
 // set per default, for maximal validation power.
 pub static RUMORPH_DEFAULT_ARGS: &[&str] =
     &["-Zalways-encode-mir", "-Zmir-opt-level=0", "--cfg=rumorph"];
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct RuMorphConfig {
     pub verbosity: Verbosity,
     pub report_level: ReportLevel,
@@ -66,10 +87,25 @@ pub struct RuMorphConfig {
     pub uninit_exposure_enabled: bool,
     pub broken_bitpatterns_enabled: bool,
     pub unsafe_dataflow_enabled: bool,
+    /// Report arithmetic and casts that may overflow (opt-in, `-Zrumorph-enable-overflow`)
     pub overflow_enabled: bool,
     pub errhandle_enabled: bool,
     pub truncation_enabled: bool,
+    /// Re-check generic conversions for every collected instantiation (opt-in, needs codegen items)
+    pub mono_layout_enabled: bool,
     pub optimize_enabled: bool,
+    /// Use the flow-sensitive taint analysis instead of the flow-insensitive place graph
+    pub flow_sensitive_enabled: bool,
+    /// Compute summaries of local functions before running the checkers
+    pub interprocedural_enabled: bool,
+    /// Write DOT graphs of the functions whose path contains this pattern (see `dump`)
+    pub dump_graph_pattern: Option<String>,
+    /// Directory the dumped graphs are written to
+    pub graph_dir: PathBuf,
+    /// Target triples whose layouts are compared besides the host's (see `target`)
+    pub layout_targets: Vec<TargetTriple>,
+    /// Marker trait knowledge base, the shipped entries by default (see `marker`)
+    pub marker_traits: MarkerTraits,
 }
 
 impl Default for RuMorphConfig {
@@ -81,10 +117,17 @@ impl Default for RuMorphConfig {
             uninit_exposure_enabled: true,
             broken_bitpatterns_enabled: true,
             unsafe_dataflow_enabled: true,
-            overflow_enabled: true,
+            overflow_enabled: false,
             errhandle_enabled: true,
             truncation_enabled: true,
+            mono_layout_enabled: false,
             optimize_enabled: true,
+            flow_sensitive_enabled: false,
+            interprocedural_enabled: true,
+            dump_graph_pattern: None,
+            graph_dir: PathBuf::from(dump::DEFAULT_GRAPH_DIR),
+            layout_targets: target::default_layout_targets(),
+            marker_traits: MarkerTraits::default(),
         }
     }
 }
@@ -123,15 +166,48 @@ where
     result
 }
 
-pub fn analyze<'tcx>(tcx: TyCtxt<'tcx>, config: RuMorphConfig) {
-    // workaround to mimic arena lifetime
-    let rcx_owner = RuMorphCtxtOwner::new(tcx, config.report_level, config.optimize_enabled);
-    let rcx = &*Box::leak(Box::new(rcx_owner));
+/// Runs the enabled analyses on the crate and returns the findings.
+/// Reports are also forwarded to the global report logger if it was initialized.
+pub fn analyze<'tcx>(tcx: TyCtxt<'tcx>, config: RuMorphConfig) -> Findings {
+    analyze_impl(tcx, config, None)
+}
+
+/// Same as `analyze`, but forwards the reports to `sink` instead of the global report logger.
+/// This doesn't depend on any process-global state, so it can be called from a custom
+/// rustc driver and for several crates in the same process.
+/// Flushing `sink` is left to the caller.
+pub fn analyze_with_sink<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    config: RuMorphConfig,
+    sink: Arc<dyn ReportLogger>,
+) -> Findings {
+    analyze_impl(tcx, config, Some(sink))
+}
+
+fn analyze_impl<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    config: RuMorphConfig,
+    sink: Option<Arc<dyn ReportLogger>>,
+) -> Findings {
+    // the context lives as long as this call, the findings are taken out before it is dropped
+    let mut rcx_owner =
+        RuMorphCtxtOwner::with_sink(tcx, config.report_level, config.optimize_enabled, sink);
+    rcx_owner.set_flow_sensitive(config.flow_sensitive_enabled);
+    rcx_owner.set_dump_graph_pattern(config.dump_graph_pattern);
+    rcx_owner.set_graph_dir(config.graph_dir);
+    rcx_owner.set_layout_targets(&config.layout_targets);
+    rcx_owner.set_marker_traits(config.marker_traits);
+    let rcx = &rcx_owner;
 
     // shadow the variable tcx
     #[allow(unused_variables)]
     let tcx = ();
 
+    // Summaries have to exist before the bodies that call them are translated
+    if config.interprocedural_enabled {
+        run_analysis("Summary", || rcx.compute_summaries())
+    }
+
     // Broken layout analysis
     //if config.broken_layout_enabled {
     //    run_analysis("BrokenLayout", || {
@@ -163,12 +239,12 @@ pub fn analyze<'tcx>(tcx: TyCtxt<'tcx>, config: RuMorphConfig) {
     //     })
     // }
 
-    // if config.overflow_enabled {
-    //     run_analysis("Overflow", || {
-    //         let checker = OverflowChecker::new(rcx);
-    //         checker.analyze();
-    //     })
-    // }
+    if config.overflow_enabled {
+        run_analysis("Overflow", || {
+            let checker = OverflowChecker::new(rcx);
+            checker.analyze();
+        })
+    }
 
     // if config.errhandle_enabled {
     //     run_analysis("ErrHandle", || {
@@ -183,4 +259,13 @@ pub fn analyze<'tcx>(tcx: TyCtxt<'tcx>, config: RuMorphConfig) {
             checker.analyze();
         })
     }
+
+    if config.mono_layout_enabled {
+        run_analysis("MonoLayout", || {
+            let checker = MonoLayoutChecker::new(rcx);
+            checker.analyze();
+        })
+    }
+
+    rcx.take_findings()
 }
@@ -0,0 +1,240 @@
+//! Knowledge base of "plain old data" marker traits.
+//! A bound like `T: zerocopy::FromBytes` says more about `T` than any of its impls: every bit
+//! pattern is a valid `T`. The shipped entries cover the usual crates, and more can be given
+//! with `-Zrumorph-marker-traits=path:guarantee+guarantee,...`.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::ty::subst::GenericArgKind;
+use rustc_middle::ty::{self, ConstKind, ParamEnv, Ty, TyCtxt, TyKind, TypeVisitableExt};
+
+use crate::progress_info;
+
+bitflags! {
+    /// What a marker trait guarantees about its implementors
+    #[derive(Default)]
+    pub struct Guarantee: u8 {
+        /// every bit pattern is a valid value
+        const ANY_BIT_PATTERN = 0b00000001;
+        /// no padding bytes, so every byte of a value is initialized
+        const NO_PADDING = 0b00000010;
+        /// aligned to 1 byte
+        const UNALIGNED = 0b00000100;
+    }
+}
+
+impl Guarantee {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "any-bit-pattern" => Guarantee::ANY_BIT_PATTERN,
+            "no-padding" => Guarantee::NO_PADDING,
+            "unaligned" => Guarantee::UNALIGNED,
+            _ => return None,
+        })
+    }
+}
+
+/// Shipped entries; traits without guarantees say nothing about a type parameter
+pub const DEFAULT_MARKER_TRAITS: &[(&str, Guarantee)] = &[
+    ("std::marker::Copy", Guarantee::empty()),
+    ("std::clone::Clone", Guarantee::empty()),
+    ("std::marker::Sized", Guarantee::empty()),
+    (
+        "bytemuck::Pod",
+        Guarantee::from_bits_truncate(Guarantee::ANY_BIT_PATTERN.bits() | Guarantee::NO_PADDING.bits()),
+    ),
+    ("bytemuck::AnyBitPattern", Guarantee::ANY_BIT_PATTERN),
+    ("bytemuck::Zeroable", Guarantee::empty()),
+    ("bytemuck::NoUninit", Guarantee::NO_PADDING),
+    ("zerocopy::FromBytes", Guarantee::ANY_BIT_PATTERN),
+    ("zerocopy::FromZeroes", Guarantee::empty()),
+    ("zerocopy::AsBytes", Guarantee::NO_PADDING),
+    ("zerocopy::Unaligned", Guarantee::UNALIGNED),
+    ("plain::Plain", Guarantee::ANY_BIT_PATTERN),
+];
+
+/// Marker traits by path, as printed by `TyCtxt::def_path_str`
+#[derive(Debug, Clone)]
+pub struct MarkerTraits {
+    entries: HashMap<String, Guarantee>,
+}
+
+impl Default for MarkerTraits {
+    fn default() -> Self {
+        MarkerTraits::new(&[])
+    }
+}
+
+impl MarkerTraits {
+    /// The shipped entries, then `specs` such as `"my::Plain:any-bit-pattern+no-padding"`;
+    /// a user entry replaces a shipped one with the same path
+    pub fn new(specs: &[&str]) -> Self {
+        let entries = DEFAULT_MARKER_TRAITS
+            .iter()
+            .map(|&(path, guarantee)| (path.to_owned(), guarantee))
+            .collect();
+        let mut marker_traits = MarkerTraits { entries };
+        marker_traits.extend(specs);
+        marker_traits
+    }
+
+    /// Adds `specs` to the entries, see `new`
+    pub fn extend(&mut self, specs: &[&str]) {
+        for spec in specs {
+            match Self::parse(spec) {
+                Some((path, guarantee)) => {
+                    self.entries.insert(path, guarantee);
+                },
+                None => warn!("Invalid marker trait `{}`", spec),
+            }
+        }
+    }
+
+    fn parse(spec: &str) -> Option<(String, Guarantee)> {
+        let (path, names) = spec.rsplit_once(':')?;
+        if path.is_empty() || path.ends_with(':') {
+            return None;
+        }
+        let mut guarantee = Guarantee::empty();
+        for name in names.split('+').filter(|name| !name.is_empty()) {
+            guarantee |= Guarantee::from_name(name)?;
+        }
+        Some((path.to_owned(), guarantee))
+    }
+
+    /// `None` for traits that aren't in the knowledge base
+    pub fn guarantee(&self, path: &str) -> Option<Guarantee> {
+        self.entries.get(path).copied()
+    }
+
+    /// Guarantees of the bounds on `ty` in `p_env`, e.g. of `T` given `T: FromBytes`
+    pub fn guarantees_of<'tcx>(&self, tcx: TyCtxt<'tcx>, p_env: ParamEnv<'tcx>, ty: Ty<'tcx>) -> Guarantee {
+        let mut guarantee = Guarantee::empty();
+        for cb in p_env.caller_bounds() {
+            if let Some(trait_pred) = cb.to_opt_poly_trait_pred()
+                && trait_pred.skip_binder().self_ty() == ty
+                && let Some(implied) = self.guarantee(&tcx.def_path_str(trait_pred.def_id()))
+            {
+                guarantee |= implied;
+            }
+        }
+        guarantee
+    }
+}
+
+/// An impl whose self type has parameters, kept symbolically, e.g. `[T; N] where T: Pod`
+#[derive(Debug, Clone)]
+pub struct GenericImpl<'tcx> {
+    pub impl_def_id: DefId,
+    /// self type over the impl's own parameters, e.g. `[T; N]`
+    pub self_ty: Ty<'tcx>,
+    /// where clauses of the impl, without the implicit `Sized` bounds
+    pub bounds: Vec<ty::PolyTraitPredicate<'tcx>>,
+}
+
+impl<'tcx> GenericImpl<'tcx> {
+    /// e.g. "[T; N] where T: bytemuck::Pod"
+    pub fn describe(&self, tcx: TyCtxt<'tcx>) -> String {
+        if self.bounds.is_empty() {
+            return self.self_ty.to_string();
+        }
+        let bounds = self
+            .bounds
+            .iter()
+            .map(|bound| format!("{}: {}", bound.skip_binder().self_ty(), tcx.def_path_str(bound.def_id())))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{} where {}", self.self_ty, bounds)
+    }
+
+    /// Binds the type parameters of `self_ty` so that it becomes `ty`, e.g. `T = u32` for `[T; N]`
+    /// and `[u32; 4]`; `None` if `ty` isn't an instance of `self_ty`
+    pub fn instantiate(&self, ty: Ty<'tcx>) -> Option<HashMap<Ty<'tcx>, Ty<'tcx>>> {
+        let mut bindings = HashMap::new();
+        match_ty(self.self_ty, ty, &mut bindings).then_some(bindings)
+    }
+}
+
+fn match_ty<'tcx>(pattern: Ty<'tcx>, ty: Ty<'tcx>, bindings: &mut HashMap<Ty<'tcx>, Ty<'tcx>>) -> bool {
+    match (pattern.kind(), ty.kind()) {
+        (TyKind::Param(_), _) => *bindings.entry(pattern).or_insert(ty) == ty,
+        (TyKind::Array(p_elem, p_len), TyKind::Array(elem, len)) => {
+            (p_len == len || matches!(p_len.kind(), ConstKind::Param(_))) && match_ty(*p_elem, *elem, bindings)
+        },
+        (TyKind::Slice(p_elem), TyKind::Slice(elem)) => match_ty(*p_elem, *elem, bindings),
+        (TyKind::Ref(_, p_pointee, p_mutbl), TyKind::Ref(_, pointee, mutbl)) => {
+            p_mutbl == mutbl && match_ty(*p_pointee, *pointee, bindings)
+        },
+        (TyKind::RawPtr(p_pointee), TyKind::RawPtr(pointee)) => {
+            p_pointee.mutbl == pointee.mutbl && match_ty(p_pointee.ty, pointee.ty, bindings)
+        },
+        (TyKind::Tuple(p_fields), TyKind::Tuple(fields)) => {
+            p_fields.len() == fields.len()
+                && p_fields.iter().zip(fields.iter()).all(|(p_field, field)| match_ty(p_field, field, bindings))
+        },
+        (TyKind::Adt(p_def, p_substs), TyKind::Adt(def, substs)) => {
+            p_def == def
+                && p_substs.iter().zip(substs.iter()).all(|(p_arg, arg)| match (p_arg.unpack(), arg.unpack()) {
+                    (GenericArgKind::Type(p_arg), GenericArgKind::Type(arg)) => match_ty(p_arg, arg, bindings),
+                    (GenericArgKind::Const(p_arg), GenericArgKind::Const(arg)) => {
+                        p_arg == arg || matches!(p_arg.kind(), ConstKind::Param(_))
+                    },
+                    // lifetimes don't change the layout
+                    _ => true,
+                })
+        },
+        _ => !pattern.has_param() && pattern == ty,
+    }
+}
+
+/// Positive impls of a trait, see `RuMorphCtxtOwner::trait_impls`
+#[derive(Debug, Default)]
+pub struct TraitImpls<'tcx> {
+    /// self types without parameters
+    pub concrete: HashSet<Ty<'tcx>>,
+    /// impls over parameters, including blanket impls
+    pub generic: Vec<GenericImpl<'tcx>>,
+}
+
+impl<'tcx> TraitImpls<'tcx> {
+    /// Impls in this crate, and upstream ones as well if `with_upstream`
+    pub fn collect(tcx: TyCtxt<'tcx>, trait_def_id: DefId, with_upstream: bool) -> Self {
+        let impl_ids: Vec<DefId> = if with_upstream {
+            tcx.all_impls(trait_def_id).collect()
+        } else {
+            tcx.hir()
+                .trait_impls(trait_def_id)
+                .iter()
+                .map(|impl_id| impl_id.to_def_id())
+                .collect()
+        };
+
+        let sized_trait = tcx.lang_items().sized_trait();
+        let mut impls = TraitImpls::default();
+        for impl_id in impl_ids {
+            if tcx.impl_polarity(impl_id) != ty::ImplPolarity::Positive {
+                continue;
+            }
+            let Some(binder) = tcx.impl_trait_ref(impl_id) else { continue };
+            let impl_ty = binder.skip_binder().self_ty();
+            if !impl_ty.has_param() {
+                impls.concrete.insert(impl_ty);
+                continue;
+            }
+
+            // e.g. `impl<T: Pod, const N: usize> Pod for [T; N]`
+            let bounds = tcx
+                .param_env(impl_id)
+                .caller_bounds()
+                .iter()
+                .filter_map(|bound| bound.to_opt_poly_trait_pred())
+                .filter(|bound| Some(bound.def_id()) != sized_trait)
+                .collect();
+            let generic_impl = GenericImpl { impl_def_id: impl_id, self_ty: impl_ty, bounds };
+            progress_info!("{} is implemented on {}", tcx.def_path_str(trait_def_id), generic_impl.describe(tcx));
+            impls.generic.push(generic_impl);
+        }
+        impls
+    }
+}
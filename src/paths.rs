@@ -61,6 +61,12 @@ pub const STR_FROM_UNCHECKED_MUT: [&str; 4] = ["core", "str", "converts", "from_
 
 pub const CHAR_FROM_UNCHECKED: [&str; 5] = ["core", "char", "methods", "<impl char>", "from_u32_unchecked"];
 
+// length of a slice or collection, trusted by the guard facts
+pub const SLICE_LEN: [&str; 4] = ["core", "slice", "<impl [T]>", "len"];
+pub const STR_LEN: [&str; 4] = ["core", "str", "<impl str>", "len"];
+pub const VEC_LEN: [&str; 4] = ["alloc", "vec", "Vec", "len"];
+pub const STRING_LEN: [&str; 4] = ["alloc", "string", "String", "len"];
+
 // for alignment
 pub const READ_UNALIGNED: [&str; 3] = ["core", "ptr", "read_unaligned"];
 pub const WRITE_UNALIGNED: [&str; 3] = ["core", "ptr", "write_unaligned"];
@@ -123,6 +129,15 @@ pub static WEAK_LIFETIME_BYPASS_LIST: Lazy<PathSet> = Lazy::new(move || {
     ])
 });
 
+pub static LEN_LIST: Lazy<PathSet> = Lazy::new(move || {
+    PathSet::new(&[
+        &SLICE_LEN,
+        &STR_LEN,
+        &VEC_LEN,
+        &STRING_LEN,
+    ])
+});
+
 pub static STR_UNCHECKED_LIST: Lazy<PathSet> = Lazy::new(move || {
     PathSet::new(&[
         &STR_FROM_UNCHECKED,
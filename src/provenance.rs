@@ -0,0 +1,144 @@
+//! Taint that remembers where it came from.
+//! `Traced<T>` pairs a taint with the origins that introduced it, so a checker can tell
+//! which argument, cast or call reaches a sink after `propagate()`.
+
+use std::fmt;
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+
+use crate::graph::GraphTaint;
+use crate::ir;
+
+/// Maximum number of origins kept per taint; the rest are only counted as `truncated`
+pub const ORIGIN_LIMIT: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Argument of the analyzed function, as a local index (`_1` is the first argument)
+    Arg(usize),
+    /// A cast statement
+    Cast(Span),
+    /// Return value of a call to the given function
+    Call(DefId),
+}
+
+impl Origin {
+    /// Human-readable description, e.g. "argument `amount`"
+    pub fn describe<'tcx>(&self, tcx: TyCtxt<'tcx>, body: &ir::Body<'tcx>) -> String {
+        match *self {
+            Origin::Arg(local) => match arg_name(body, local) {
+                Some(name) => format!("argument `{}`", name),
+                None => format!("argument #{}", local),
+            },
+            Origin::Cast(span) => {
+                let line = tcx.sess.source_map().lookup_char_pos(span.lo()).line;
+                format!("the cast at line {}", line)
+            },
+            Origin::Call(def_id) => format!("the result of `{}`", tcx.def_path_str(def_id)),
+        }
+    }
+}
+
+fn arg_name(body: &ir::Body<'_>, local: usize) -> Option<String> {
+    body.original.var_debug_info.iter().find_map(|info| match &info.value {
+        mir::VarDebugInfoContents::Place(place)
+            if place.local.index() == local && place.projection.is_empty() =>
+        {
+            Some(info.name.to_string())
+        },
+        _ => None,
+    })
+}
+
+/// A taint `T` with up to `ORIGIN_LIMIT` origins
+#[derive(Clone, Default)]
+pub struct Traced<T> {
+    pub taint: T,
+    origins: Vec<Origin>,
+    truncated: bool,
+}
+
+impl<T: GraphTaint> Traced<T> {
+    pub fn new(taint: T, origin: Origin) -> Self {
+        Traced {
+            taint,
+            origins: vec![origin],
+            truncated: false,
+        }
+    }
+
+    pub fn origins(&self) -> &[Origin] {
+        &self.origins
+    }
+
+    /// Whether some origins were dropped because of `ORIGIN_LIMIT`
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// "argument `a` and argument `b`", or `None` without origins
+    pub fn describe<'tcx>(&self, tcx: TyCtxt<'tcx>, body: &ir::Body<'tcx>) -> Option<String> {
+        if self.origins.is_empty() {
+            return None;
+        }
+        let mut description = self
+            .origins
+            .iter()
+            .map(|origin| origin.describe(tcx, body))
+            .collect::<Vec<_>>()
+            .join(" and ");
+        if self.truncated {
+            description.push_str(" (and more)");
+        }
+        Some(description)
+    }
+}
+
+impl<T: GraphTaint> GraphTaint for Traced<T> {
+    fn is_empty(&self) -> bool {
+        self.taint.is_empty()
+    }
+
+    // Once truncated, new origins are no longer recorded, which keeps propagation finite
+    fn contains(&self, taint: &Self) -> bool {
+        self.taint.contains(&taint.taint)
+            && (self.truncated
+                || (!taint.truncated && taint.origins.iter().all(|origin| self.origins.contains(origin))))
+    }
+
+    fn join(&mut self, taint: &Self) {
+        self.taint.join(&taint.taint);
+        self.truncated |= taint.truncated;
+        for origin in &taint.origins {
+            if self.origins.contains(origin) {
+                continue;
+            }
+            if self.origins.len() < ORIGIN_LIMIT {
+                self.origins.push(*origin);
+            } else {
+                self.truncated = true;
+            }
+        }
+    }
+
+    fn remove(&mut self, taint: &Self) {
+        self.taint.remove(&taint.taint);
+        if T::default().contains(&self.taint) {
+            self.origins.clear();
+            self.truncated = false;
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Traced<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} from {:?}", self.taint, self.origins)?;
+        if self.truncated {
+            write!(f, "..")?;
+        }
+        Ok(())
+    }
+}
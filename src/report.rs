@@ -8,7 +8,7 @@ use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
 use std::env;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 use toml;
@@ -47,11 +47,25 @@ pub fn default_report_logger() -> Box<dyn ReportLogger> {
     }
 }
 
+/// Logs `report` to the global report logger, or straight to stderr if it wasn't initialized.
+/// Checkers use `RuMorphCtxtOwner::report`, which also returns the report in the findings.
 pub fn rumorph_report(report: Report) {
-    REPORT_LOGGER.get().unwrap().log(report);
+    match global_report_logger() {
+        Some(logger) => logger.log(report),
+        None => {
+            let logger = StderrLogger::new();
+            logger.log(report);
+            logger.flush();
+        },
+    }
 }
 
-#[derive(Serialize, Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+/// Returns the process-global report logger if `init_report_logger` was called.
+pub fn global_report_logger() -> Option<&'static dyn ReportLogger> {
+    REPORT_LOGGER.get().map(|logger| &**logger)
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum ReportLevel {
     // Rank: High
     Error = 2,
@@ -67,7 +81,7 @@ impl fmt::Display for ReportLevel {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Report {
     level: ReportLevel,
     analyzer: Cow<'static, str>,
@@ -136,6 +150,72 @@ impl Report {
             source: color_span.to_colored_string(),
         }
     }
+
+    pub fn level(&self) -> ReportLevel {
+        self.level
+    }
+
+    pub fn analyzer(&self) -> &str {
+        &self.analyzer
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+/// All reports produced by a single `analyze` run.
+#[derive(Serialize, Default, Debug)]
+pub struct Findings {
+    reports: Vec<Report>,
+}
+
+impl Findings {
+    pub fn new(reports: Vec<Report>) -> Self {
+        Findings { reports }
+    }
+
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    pub fn into_reports(self) -> Vec<Report> {
+        self.reports
+    }
+
+    pub fn len(&self) -> usize {
+        self.reports.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reports.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Report> {
+        self.reports.iter()
+    }
+
+    /// Reports with a level greater than or equal to `level`
+    pub fn at_least(&self, level: ReportLevel) -> impl Iterator<Item = &Report> {
+        self.reports.iter().filter(move |report| report.level >= level)
+    }
+}
+
+impl IntoIterator for Findings {
+    type Item = Report;
+    type IntoIter = std::vec::IntoIter<Report>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.reports.into_iter()
+    }
 }
 
 pub trait ReportLogger: Sync + Send {
@@ -143,12 +223,12 @@ pub trait ReportLogger: Sync + Send {
     fn flush(&self);
 }
 
-struct StderrLogger {
+pub struct StderrLogger {
     reports: Mutex<Vec<Report>>,
 }
 
 impl StderrLogger {
-    fn new() -> Self {
+    pub fn new() -> Self {
         StderrLogger {
             reports: Mutex::new(Vec::new()),
         }
@@ -180,13 +260,13 @@ impl ReportLogger for StderrLogger {
     }
 }
 
-struct FileLogger {
+pub struct FileLogger {
     reports: Mutex<Vec<Report>>,
     file_path: PathBuf,
 }
 
 impl FileLogger {
-    fn new<T>(val: T) -> Self
+    pub fn new<T>(val: T) -> Self
     where
         T: Into<PathBuf>,
     {
@@ -0,0 +1,216 @@
+//! Call-target resolution used while translating MIR into RuMorph IR.
+//! Direct calls keep their syntactic callee; trait method calls are resolved with
+//! `Instance::resolve` when the substitutions are concrete enough, and indirect calls
+//! (fn pointers, closures called through `Fn*`, `dyn Trait` methods) get a candidate set.
+
+use std::collections::HashMap;
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::{self, CastKind, Operand, Rvalue, StatementKind};
+use rustc_middle::ty::{self, Instance, InstanceDef, InternalSubsts, SubstsRef, TyCtxt, TyKind};
+
+use crate::ir::{CallKind, Callee};
+use crate::iter::LocalTraitIter;
+
+pub enum ResolvedCall<'tcx> {
+    /// A `FnDef` callee, along with the impl item it resolves to when it is a trait method
+    Static {
+        callee: Callee<'tcx>,
+        resolved: Option<Callee<'tcx>>,
+    },
+    Indirect {
+        kind: CallKind,
+        callees: Vec<Callee<'tcx>>,
+    },
+}
+
+pub struct CallResolver<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    param_env: ty::ParamEnv<'tcx>,
+    body: &'a mir::Body<'tcx>,
+    /// Fn items reified into each local, see `collect_reified_fns`
+    reified_fns: HashMap<mir::Local, Vec<Callee<'tcx>>>,
+}
+
+impl<'a, 'tcx> CallResolver<'a, 'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>, body: &'a mir::Body<'tcx>) -> Self {
+        CallResolver {
+            tcx,
+            param_env: tcx.param_env(body.source.def_id()),
+            body,
+            reified_fns: collect_reified_fns(tcx, body),
+        }
+    }
+
+    pub fn resolve(&self, func: &Operand<'tcx>) -> ResolvedCall<'tcx> {
+        match func {
+            Operand::Constant(box func) => match func.literal.ty().kind() {
+                TyKind::FnDef(def_id, substs) => self.resolve_fn_def(*def_id, substs),
+                _ => ResolvedCall::Indirect {
+                    kind: CallKind::FnPtr,
+                    callees: Vec::new(),
+                },
+            },
+            Operand::Copy(place) | Operand::Move(place) => {
+                let func_ty = place.ty(self.body, self.tcx).ty;
+                match func_ty.kind() {
+                    // zero-sized fn item stored in a local
+                    TyKind::FnDef(def_id, substs) => self.resolve_fn_def(*def_id, substs),
+                    _ => ResolvedCall::Indirect {
+                        kind: CallKind::FnPtr,
+                        callees: place
+                            .as_local()
+                            .and_then(|local| self.reified_fns.get(&local).cloned())
+                            .unwrap_or_default(),
+                    },
+                }
+            },
+        }
+    }
+
+    fn resolve_fn_def(&self, def_id: DefId, substs: SubstsRef<'tcx>) -> ResolvedCall<'tcx> {
+        let tcx = self.tcx;
+        let callee = Callee { did: def_id, substs };
+        let trait_did = match tcx.trait_of_item(def_id) {
+            Some(trait_did) => trait_did,
+            None => {
+                return ResolvedCall::Static {
+                    callee,
+                    resolved: None,
+                }
+            },
+        };
+
+        if tcx.fn_trait_kind_from_def_id(trait_did).is_some() {
+            return ResolvedCall::Indirect {
+                kind: CallKind::Closure,
+                callees: self.closure_callees(def_id, substs),
+            };
+        }
+
+        match self.resolve_instance(def_id, substs) {
+            Some(Instance {
+                def: InstanceDef::Virtual(..),
+                ..
+            }) => ResolvedCall::Indirect {
+                kind: CallKind::Virtual,
+                callees: self.local_impl_callees(trait_did, def_id),
+            },
+            Some(Instance {
+                def: InstanceDef::Item(did),
+                substs,
+            }) if did != def_id => ResolvedCall::Static {
+                callee,
+                resolved: Some(Callee { did, substs }),
+            },
+            _ => ResolvedCall::Static {
+                callee,
+                resolved: None,
+            },
+        }
+    }
+
+    /// The first substitution of `Fn*::call*` is the callable itself.
+    fn closure_callees(&self, def_id: DefId, substs: SubstsRef<'tcx>) -> Vec<Callee<'tcx>> {
+        let mut callable_ty = substs.type_at(0);
+        while let TyKind::Ref(_, inner, _) = callable_ty.kind() {
+            callable_ty = *inner;
+        }
+
+        match callable_ty.kind() {
+            TyKind::Closure(did, substs) | TyKind::FnDef(did, substs) => vec![Callee {
+                did: *did,
+                substs,
+            }],
+            _ => match self.resolve_instance(def_id, substs) {
+                Some(Instance {
+                    def: InstanceDef::Item(did),
+                    substs,
+                }) if did != def_id => vec![Callee { did, substs }],
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    fn resolve_instance(&self, def_id: DefId, substs: SubstsRef<'tcx>) -> Option<Instance<'tcx>> {
+        Instance::resolve(self.tcx, self.param_env, def_id, substs)
+            .ok()
+            .flatten()
+    }
+
+    /// All local implementations of `trait_item`, or the default body when an impl doesn't override it.
+    fn local_impl_callees(&self, trait_did: DefId, trait_item: DefId) -> Vec<Callee<'tcx>> {
+        let tcx = self.tcx;
+        LocalTraitIter::from_tcx(tcx, trait_did)
+            .filter_map(|impl_did| {
+                tcx.impl_item_implementor_ids(impl_did.to_def_id())
+                    .get(&trait_item)
+                    .copied()
+                    .or_else(|| tcx.associated_item(trait_item).defaultness(tcx).has_value().then_some(trait_item))
+            })
+            .map(|did| Callee {
+                did,
+                substs: InternalSubsts::identity_for_item(tcx, did),
+            })
+            .collect()
+    }
+}
+
+/// Fn items reified into each local of `body`, directly or through copies and pointer casts, e.g.
+/// `_5 = foo as fn() (Pointer(ReifyFnPointer)); _6 = _5`
+fn collect_reified_fns<'tcx>(tcx: TyCtxt<'tcx>, body: &mir::Body<'tcx>) -> HashMap<mir::Local, Vec<Callee<'tcx>>> {
+    let mut reified: HashMap<mir::Local, Vec<Callee<'tcx>>> = HashMap::new();
+    // (from, to) pairs of locals holding the same fn pointer
+    let mut copies = Vec::new();
+    for bb in body.basic_blocks.iter() {
+        for statement in &bb.statements {
+            let StatementKind::Assign(box (lplace, rval)) = &statement.kind else { continue };
+            let Some(local) = lplace.as_local() else { continue };
+            let operand = match rval {
+                Rvalue::Cast(CastKind::Pointer(_), operand, _) | Rvalue::Use(operand) => operand,
+                _ => continue,
+            };
+            match operand {
+                Operand::Constant(box func) => {
+                    if let TyKind::FnDef(did, substs) = func.literal.ty().kind() {
+                        push_callee(reified.entry(local).or_default(), Callee { did: *did, substs });
+                    }
+                },
+                Operand::Copy(place) | Operand::Move(place) => match place.ty(body, tcx).ty.kind() {
+                    // zero-sized fn item stored in a local, then reified
+                    TyKind::FnDef(did, substs) => {
+                        push_callee(reified.entry(local).or_default(), Callee { did: *did, substs });
+                    },
+                    _ => {
+                        if let Some(from) = place.as_local() {
+                            copies.push((from, local));
+                        }
+                    },
+                },
+            }
+        }
+    }
+
+    // each round follows one more link of a copy chain
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(from, to) in &copies {
+            let Some(callees) = reified.get(&from).cloned() else { continue };
+            let targets = reified.entry(to).or_default();
+            for callee in callees {
+                changed |= push_callee(targets, callee);
+            }
+        }
+    }
+    reified
+}
+
+// false if `callee` is already in `callees`
+fn push_callee<'tcx>(callees: &mut Vec<Callee<'tcx>>, callee: Callee<'tcx>) -> bool {
+    if callees.iter().any(|known| known.did == callee.did && known.substs == callee.substs) {
+        return false;
+    }
+    callees.push(callee);
+    true
+}
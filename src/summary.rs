@@ -0,0 +1,145 @@
+//! Interprocedural summaries of local functions.
+//! A summary tells a caller which arguments reach the callee's return value, which arguments are
+//! written through a pointer argument, and which arguments are dereferenced inside the callee.
+//! Summaries are computed bottom-up (see `RuMorphCtxtOwner::compute_summaries`), so translating a
+//! caller can replace the conservative "every argument flows into `dest`" edges of a call.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::{self, StatementKind};
+use rustc_middle::ty::TyCtxt;
+
+use crate::graph::Graph;
+use crate::ir;
+
+#[derive(Debug, Default, Clone)]
+pub struct FnSummary {
+    /// Arguments (0-based) whose value reaches the return place
+    pub arg_to_ret: Vec<usize>,
+    /// `(from, to)`: argument `from` is written through the pointer argument `to`
+    pub arg_to_arg_pointee: Vec<(usize, usize)>,
+    /// Arguments dereferenced in the callee or in one of its summarized callees
+    pub deref_args: Vec<usize>,
+}
+
+impl FnSummary {
+    /// `summary_of` returns the summaries of the callees that are already computed.
+    pub fn compute(
+        body: &ir::Body<'_>,
+        summary_of: impl Fn(DefId) -> Option<Rc<FnSummary>>,
+    ) -> Self {
+        let arg_count = body.original.arg_count;
+        let deref_locals = deref_locals(body, summary_of);
+
+        let mut summary = FnSummary::default();
+        for arg in 0..arg_count {
+            let reachable = reachable_nodes(body, arg + 1);
+
+            let reaches_local = |local: usize, pointee_only: bool| {
+                body.place_index
+                    .projections_of(local)
+                    .into_iter()
+                    .filter(|&id| {
+                        !pointee_only
+                            || body.place_index.node(id).path.first() == Some(&ir::PlaceStep::Deref)
+                    })
+                    .any(|id| reachable[id])
+            };
+
+            if reaches_local(mir::RETURN_PLACE.index(), false) {
+                summary.arg_to_ret.push(arg);
+            }
+            for other in (0..arg_count).filter(|&other| other != arg) {
+                if reaches_local(other + 1, true) {
+                    summary.arg_to_arg_pointee.push((arg, other));
+                }
+            }
+            if deref_locals.iter().any(|&local| reaches_local(local, false)) {
+                summary.deref_args.push(arg);
+            }
+        }
+
+        summary
+    }
+
+    /// Dataflow edges `(read place, written place)` of a call to the summarized function
+    pub fn call_flows<'tcx>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        args: &[mir::Operand<'tcx>],
+        dest: mir::Place<'tcx>,
+    ) -> Vec<(mir::Place<'tcx>, mir::Place<'tcx>)> {
+        let arg_place = |idx: usize| args.get(idx).and_then(|arg| arg.place());
+
+        let mut flows = Vec::new();
+        for &arg in &self.arg_to_ret {
+            if let Some(place) = arg_place(arg) {
+                flows.push((place, dest));
+            }
+        }
+        for &(from, to) in &self.arg_to_arg_pointee {
+            if let (Some(from), Some(to)) = (arg_place(from), arg_place(to)) {
+                flows.push((from, tcx.mk_place_deref(to)));
+            }
+        }
+        flows
+    }
+}
+
+fn reachable_nodes(body: &ir::Body<'_>, start: usize) -> Vec<bool> {
+    let mut visited = vec![false; body.len()];
+    let mut work_list = VecDeque::new();
+
+    visited[start] = true;
+    work_list.push_back(start);
+    while let Some(current) = work_list.pop_front() {
+        for &next in body.next(current) {
+            if !visited[next] {
+                visited[next] = true;
+                work_list.push_back(next);
+            }
+        }
+    }
+    visited
+}
+
+/// Locals used as the base of a dereference, including pointers handed to a callee that dereferences them
+fn deref_locals(
+    body: &ir::Body<'_>,
+    summary_of: impl Fn(DefId) -> Option<Rc<FnSummary>>,
+) -> Vec<usize> {
+    let mut locals = Vec::new();
+    let mut push = |local: usize| {
+        if !locals.contains(&local) {
+            locals.push(local);
+        }
+    };
+
+    for statement in body.basic_blocks.iter().flat_map(|bb| bb.statements.iter()) {
+        if let StatementKind::Assign(box (lplace, rval)) = &statement.kind {
+            for place in std::iter::once(*lplace).chain(ir::rvalue_reads(rval)) {
+                if place.is_indirect() {
+                    push(place.local.index());
+                }
+            }
+        }
+    }
+
+    for terminator in body.terminators() {
+        if let ir::TerminatorKind::StaticCall { args, .. } = &terminator.kind {
+            let summary = terminator.static_callee().and_then(&summary_of);
+            for (idx, place) in args.iter().enumerate().filter_map(|(idx, arg)| Some((idx, arg.place()?))) {
+                let deref_in_callee = summary
+                    .as_ref()
+                    .map_or(false, |summary| summary.deref_args.contains(&idx));
+                if place.is_indirect() || deref_in_callee {
+                    push(place.local.index());
+                }
+            }
+        }
+    }
+
+    locals
+}
@@ -0,0 +1,150 @@
+//! Sizes and alignments of types on targets other than the host.
+//! `tcx.layout_of` only knows the host, but e.g. `u64` is 4-byte aligned on i686 and a
+//! pointer is 4 bytes on wasm32. The data layouts come from the rustc target specs, and the
+//! layouts of primitives, pointers, arrays, tuples and structs are recomputed from them.
+
+use rustc_middle::ty::layout::IntegerExt;
+use rustc_middle::ty::{self, ParamEnv, Ty, TyCtxt};
+use rustc_session::Session;
+use rustc_target::abi::{Integer, TargetDataLayout};
+use rustc_target::spec::{Target, TargetTriple};
+
+/// Targets checked when no `-Zrumorph-layout-targets` is given
+pub const DEFAULT_LAYOUT_TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "i686-unknown-linux-gnu",
+    "aarch64-unknown-linux-gnu",
+    "armv7-unknown-linux-gnueabihf",
+    "wasm32-unknown-unknown",
+    "riscv32imac-unknown-none-elf",
+];
+
+/// `DEFAULT_LAYOUT_TARGETS` as triples
+pub fn default_layout_targets() -> Vec<TargetTriple> {
+    DEFAULT_LAYOUT_TARGETS.iter().map(|triple| TargetTriple::from_triple(triple)).collect()
+}
+
+pub struct TargetLayout {
+    /// Architecture part of the triple, used in reports
+    pub name: String,
+    pub data_layout: TargetDataLayout,
+}
+
+impl TargetLayout {
+    /// Data layout of a built-in target or of a target JSON file in the sysroot
+    pub fn load(sess: &Session, target_triple: &TargetTriple) -> Option<Self> {
+        let triple = target_triple.triple();
+        let target = match Target::search(target_triple, &sess.sysroot) {
+            Ok((target, _)) => target,
+            Err(e) => {
+                warn!("Unknown layout target `{}`: {}", triple, e);
+                return None;
+            },
+        };
+        match target.parse_data_layout() {
+            Ok(data_layout) => Some(TargetLayout {
+                name: triple.split('-').next().unwrap_or(triple).to_owned(),
+                data_layout,
+            }),
+            Err(_) => {
+                warn!("Invalid data layout of target `{}`", triple);
+                None
+            },
+        }
+    }
+}
+
+/// Size and ABI alignment in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeAlign {
+    pub size: u64,
+    pub align: u64,
+}
+
+fn round_up(size: u64, align: u64) -> u64 {
+    (size + align - 1) / align * align
+}
+
+/// Lays out fields in order, as `repr(C)` does
+fn sequential(fields: &[SizeAlign]) -> SizeAlign {
+    let mut size = 0;
+    let mut align = 1;
+    for field in fields {
+        size = round_up(size, field.align) + field.size;
+        align = align.max(field.align);
+    }
+    SizeAlign { size: round_up(size, align), align }
+}
+
+/// Layout of `ty` on the target described by `dl`, or `None` for types whose layout isn't
+/// recomputed (enums, unions, generics, ...)
+pub fn layout_on<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    ty: Ty<'tcx>,
+    dl: &TargetDataLayout,
+) -> Option<SizeAlign> {
+    let integer = |integer: Integer| SizeAlign {
+        size: integer.size().bytes(),
+        align: integer.align(dl).abi.bytes(),
+    };
+    let pointer = |pointee: Ty<'tcx>| {
+        let size = dl.pointer_size.bytes();
+        let words = if pointee.is_sized(tcx, param_env) { 1 } else { 2 };
+        SizeAlign { size: size * words, align: dl.pointer_align.abi.bytes() }
+    };
+
+    match ty.kind() {
+        ty::Bool => Some(SizeAlign { size: 1, align: 1 }),
+        ty::Char => Some(integer(Integer::I32)),
+        ty::Int(int_ty) => Some(integer(Integer::from_int_ty(dl, *int_ty))),
+        ty::Uint(uint_ty) => Some(integer(Integer::from_uint_ty(dl, *uint_ty))),
+        ty::Float(ty::FloatTy::F32) => Some(SizeAlign { size: 4, align: dl.f32_align.abi.bytes() }),
+        ty::Float(ty::FloatTy::F64) => Some(SizeAlign { size: 8, align: dl.f64_align.abi.bytes() }),
+        ty::RawPtr(ty::TypeAndMut { ty: pointee, .. }) | ty::Ref(_, pointee, _) => Some(pointer(*pointee)),
+        ty::FnPtr(_) => Some(pointer(tcx.types.unit)),
+        ty::Array(elem, len) => {
+            let elem = layout_on(tcx, param_env, *elem, dl)?;
+            let len = len.try_eval_target_usize(tcx, param_env)?;
+            Some(SizeAlign { size: elem.size * len, align: elem.align })
+        },
+        ty::Tuple(fields) => {
+            let fields: Option<Vec<_>> = fields.iter().map(|field| layout_on(tcx, param_env, field, dl)).collect();
+            Some(reordered(fields?))
+        },
+        ty::Adt(adt_def, substs) if adt_def.is_struct() => {
+            let fields: Option<Vec<_>> = adt_def
+                .non_enum_variant()
+                .fields
+                .iter()
+                .map(|field| layout_on(tcx, param_env, field.ty(tcx, substs), dl))
+                .collect();
+            let repr = adt_def.repr();
+            let mut fields = fields?;
+            // `repr(packed(N))` caps the alignment of every field, and keeps their order
+            if let Some(pack) = repr.pack {
+                for field in &mut fields {
+                    field.align = field.align.min(pack.bytes());
+                }
+            }
+            let mut layout = if repr.c() || repr.transparent() || repr.packed() {
+                sequential(&fields)
+            } else {
+                reordered(fields)
+            };
+            if let Some(align) = repr.align {
+                layout.align = layout.align.max(align.bytes());
+                layout.size = round_up(layout.size, layout.align);
+            }
+            Some(layout)
+        },
+        _ => None,
+    }
+}
+
+/// `repr(Rust)` layout: rustc sorts the fields by decreasing alignment, which leaves no padding
+/// between them
+fn reordered(mut fields: Vec<SizeAlign>) -> SizeAlign {
+    fields.sort_by(|a, b| b.align.cmp(&a.align));
+    sequential(&fields)
+}
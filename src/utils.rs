@@ -1,3 +1,4 @@
+use rustc_hir::{def::DefKind, Unsafety};
 use rustc_middle::ty::{Instance, InstanceDef, TyCtxt};
 use rustc_middle::mir::pretty::write_mir_pretty;
 use rustc_span::{CharPos, Span, def_id::DefId};
@@ -293,7 +294,22 @@ pub fn print_mir_to_file<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>, outp
     }
 }
 
+/// The item a closure, async block or generator is defined in; other items are returned as is.
+pub fn enclosing_item<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> DefId {
+    tcx.typeck_root_def_id(def_id)
+}
+
+/// Whether `def_id`, or the function enclosing it if it is a closure, is an `unsafe fn`.
+/// Consts and statics are never unsafe.
+pub fn is_unsafe_fn<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
+    let def_id = enclosing_item(tcx, def_id);
+    matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn)
+        && tcx.fn_sig(def_id).skip_binder().unsafety() == Unsafety::Unsafe
+}
+
+/// Closures are checked with the visibility of their enclosing item.
 pub fn check_visibility<'tcx>(tcx: TyCtxt<'tcx>, func_defid: DefId) -> bool {
+    let func_defid = enclosing_item(tcx, func_defid);
     // return false if function can't be reachable to users
     let visible = tcx.visibility(func_defid).is_public();
     if visible == false {
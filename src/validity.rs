@@ -0,0 +1,173 @@
+//! Validity ranges of types, read from the scalar `valid_range`s and niches of their layouts.
+//! A `bool` only accepts 0 and 1, a reference or a `NonZeroU32` only non-zero values and an
+//! enum only its discriminants; a struct is restricted wherever one of its fields is.
+
+use rustc_middle::ty::layout::{LayoutCx, TyAndLayout};
+use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind};
+use rustc_target::abi::{Abi, FieldsShape, Scalar, Size, VariantIdx, Variants, WrappingRange};
+
+/// A scalar of a type that doesn't accept every bit pattern
+#[derive(Debug, Clone)]
+pub struct Restriction<'tcx> {
+    /// field path from the outer type, e.g. `Header.kind`
+    pub path: String,
+    /// type of the field at `path`
+    pub ty: Ty<'tcx>,
+    /// offset of the scalar in the outer type
+    pub offset: Size,
+    pub size: Size,
+    pub valid_range: WrappingRange,
+}
+
+impl<'tcx> Restriction<'tcx> {
+    /// e.g. "`Header.kind: Kind` only accepts 0..=2"
+    pub fn describe(&self) -> String {
+        let WrappingRange { start, end } = self.valid_range;
+        let range = if start == 1 && end == self.size.unsigned_int_max() {
+            "non-zero values".to_owned()
+        } else if start <= end {
+            format!("{}..={}", start, end)
+        } else {
+            format!("{}..={} (wrapping)", start, end)
+        };
+        if self.path == self.ty.to_string() {
+            format!("`{}` only accepts {}", self.ty, range)
+        } else {
+            format!("`{}: {}` only accepts {}", self.path, self.ty, range)
+        }
+    }
+}
+
+/// e.g. " (`Header.kind: Kind` only accepts 0..=2)", or empty
+pub fn note(restrictions: &[Restriction<'_>]) -> String {
+    if restrictions.is_empty() {
+        return String::new();
+    }
+    let descriptions: Vec<_> = restrictions.iter().map(Restriction::describe).collect();
+    format!(" ({})", descriptions.join("; "))
+}
+
+/// Restrictions of `to` that a value of `from` may violate, by comparing the scalars at the same
+/// offset: a restriction of `to` holds if `from` has a scalar of the same size there whose valid
+/// range lies within it. Where `from` has no restricted scalar, e.g. in `[u8; 4]`, any value is possible.
+pub fn violated<'tcx>(from: &[Restriction<'tcx>], to: &[Restriction<'tcx>]) -> Vec<Restriction<'tcx>> {
+    to.iter()
+        .filter(|target| {
+            !from.iter().any(|source| {
+                source.offset == target.offset
+                    && source.size == target.size
+                    && range_within(source.valid_range, target.valid_range, target.size)
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+// whether every value of `inner` is in `outer`, both being ranges of `size`d scalars
+fn range_within(inner: WrappingRange, outer: WrappingRange, size: Size) -> bool {
+    let intervals = |range: WrappingRange| {
+        if range.start <= range.end {
+            vec![(range.start, range.end)]
+        } else {
+            vec![(range.start, size.unsigned_int_max()), (0, range.end)]
+        }
+    };
+    let outer = intervals(outer);
+    intervals(inner)
+        .into_iter()
+        .all(|(start, end)| outer.iter().any(|&(outer_start, outer_end)| outer_start <= start && end <= outer_end))
+}
+
+/// Restricted scalars of `layout`, in field order
+pub fn restrictions<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    layout: TyAndLayout<'tcx>,
+) -> Vec<Restriction<'tcx>> {
+    let cx = LayoutCx { tcx, param_env };
+    let mut restrictions = Vec::new();
+    collect(&cx, layout, layout.ty.to_string(), Size::ZERO, &mut restrictions);
+    restrictions
+}
+
+fn collect<'tcx>(
+    cx: &LayoutCx<'tcx, TyCtxt<'tcx>>,
+    layout: TyAndLayout<'tcx>,
+    path: String,
+    offset: Size,
+    restrictions: &mut Vec<Restriction<'tcx>>,
+) {
+    match &layout.variants {
+        // only the tag is checked, what else is valid depends on the variant
+        Variants::Multiple { tag, tag_field, .. } => {
+            push(cx, layout, &path, *tag, offset + layout.fields.offset(*tag_field), restrictions);
+            return;
+        },
+        Variants::Single { index } => {
+            let before = restrictions.len();
+            // a pointer is a leaf, its pointee isn't part of its value
+            let is_pointer = matches!(layout.ty.kind(), TyKind::Ref(..) | TyKind::RawPtr(_) | TyKind::FnPtr(_));
+            match &layout.fields {
+                _ if is_pointer => {},
+                FieldsShape::Primitive | FieldsShape::Union(_) => {},
+                // every element is restricted alike, the first one stands for all
+                FieldsShape::Array { count, .. } => {
+                    if *count > 0 {
+                        collect(cx, layout.field(cx, 0), format!("{}[_]", path), offset, restrictions);
+                    }
+                },
+                FieldsShape::Arbitrary { .. } => {
+                    for i in 0..layout.fields.count() {
+                        let field_path = format!("{}.{}", path, field_name(layout.ty, *index, i));
+                        let field_offset = offset + layout.fields.offset(i);
+                        collect(cx, layout.field(cx, i), field_path, field_offset, restrictions);
+                    }
+                },
+            }
+            if restrictions.len() > before {
+                return;
+            }
+        },
+    }
+
+    // a niche of the type itself, e.g. `NonZeroU32` whose only field accepts every value
+    match layout.abi {
+        Abi::Scalar(scalar) => push(cx, layout, &path, scalar, offset, restrictions),
+        Abi::ScalarPair(first, second) => {
+            push(cx, layout, &path, first, offset, restrictions);
+            let second_offset = offset + first.size(cx).align_to(second.align(cx).abi);
+            push(cx, layout, &path, second, second_offset, restrictions);
+        },
+        _ => {},
+    }
+}
+
+fn push<'tcx>(
+    cx: &LayoutCx<'tcx, TyCtxt<'tcx>>,
+    layout: TyAndLayout<'tcx>,
+    path: &str,
+    scalar: Scalar,
+    offset: Size,
+    restrictions: &mut Vec<Restriction<'tcx>>,
+) {
+    if !scalar.is_always_valid(cx) {
+        restrictions.push(Restriction {
+            path: path.to_owned(),
+            ty: layout.ty,
+            offset,
+            size: scalar.size(cx),
+            valid_range: scalar.valid_range(cx),
+        });
+    }
+}
+
+fn field_name(ty: Ty<'_>, variant: VariantIdx, i: usize) -> String {
+    match ty.kind() {
+        TyKind::Adt(adt_def, _) => match adt_def.variant(variant).fields.iter().nth(i) {
+            Some(field) => field.name.to_string(),
+            None => i.to_string(),
+        },
+        // tuples and closures
+        _ => i.to_string(),
+    }
+}
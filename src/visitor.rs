@@ -2,7 +2,7 @@ use rustc_data_structures::fx::FxHashMap;
 use rustc_hir::{
     def_id::{DefId, LocalDefId},
     intravisit,
-    Block, BodyId, HirId, Impl, ItemKind,
+    Block, BodyId, Expr, ExprKind, HirId, Impl, ItemKind,
 };
 use rustc_middle::ty::{Ty, TyCtxt, TyKind};
 use rustc_middle::hir::nested_filter;
@@ -10,6 +10,7 @@ use rustc_span::Span;
 
 /// Maps `HirId` of a type to `BodyId` of related impls.
 /// Free-standing (top level) functions and default trait impls have `None` as a key.
+/// Closures, async blocks and generators are recorded with the key and span of their enclosing item.
 pub type RelatedItemMap = FxHashMap<Option<HirId>, Vec<(BodyId, Span)>>;
 
 /// Creates `AdtItemMap` with the given HIR map.
@@ -33,7 +34,22 @@ impl<'tcx> RelatedFnCollector<'tcx> {
     }
 }
 
+impl<'tcx> RelatedFnCollector<'tcx> {
+    /// `body_id` followed by the bodies of the closures, async blocks and generators nested in it.
+    /// Nested bodies share `span` so that their findings point at the enclosing item.
+    fn with_nested_bodies(&self, body_id: BodyId, span: Span) -> Vec<(BodyId, Span)> {
+        let mut bodies = vec![(body_id, span)];
+        bodies.extend(
+            NestedBodyCollector::collect(self.tcx, body_id)
+                .into_iter()
+                .map(|nested_body_id| (nested_body_id, span)),
+        );
+        bodies
+    }
+}
+
 impl<'tcx> intravisit::Visitor<'tcx> for RelatedFnCollector<'tcx> {
+    // Nested `fn` items are items of their own, so this is called for them as well.
     fn visit_item(&mut self, item: &'tcx rustc_hir::Item<'tcx>) {
         let hir_map = self.tcx.hir();
         match &item.kind {
@@ -44,30 +60,44 @@ impl<'tcx> intravisit::Visitor<'tcx> for RelatedFnCollector<'tcx> {
                 items: impl_items,
                 ..
             }) => {
+                let bodies: Vec<_> = impl_items
+                    .iter()
+                    .filter_map(|impl_item_ref| {
+                        let hir_id = impl_item_ref.id.hir_id();
+                        hir_map
+                            .maybe_body_owned_by(hir_id.owner.def_id)
+                            .map(|body_id| self.with_nested_bodies(body_id, impl_item_ref.span))
+                    })
+                    .flatten()
+                    .collect();
                 let key = Some(self_ty.hir_id);
-                let entry = self.hash_map.entry(key).or_insert(Vec::new());
-                entry.extend(impl_items.iter().filter_map(|impl_item_ref| {
-                    let hir_id = impl_item_ref.id.hir_id();
-                    hir_map
-                        .maybe_body_owned_by(hir_id.owner.def_id)
-                        .map(|body_id| (body_id, impl_item_ref.span))
-                }));
+                self.hash_map.entry(key).or_insert(Vec::new()).extend(bodies);
             }
             // Free-standing (top level) functions and default trait impls have `None` as a key.
             ItemKind::Trait(_is_auto, _unsafety, _generics, _generic_bounds, trait_items) => {
+                let bodies: Vec<_> = trait_items
+                    .iter()
+                    .filter_map(|trait_item_ref| {
+                        let hir_id = trait_item_ref.id.hir_id();
+                        hir_map
+                            .maybe_body_owned_by(hir_id.owner.def_id)
+                            .map(|body_id| self.with_nested_bodies(body_id, trait_item_ref.span))
+                    })
+                    .flatten()
+                    .collect();
                 let key = None;
-                let entry = self.hash_map.entry(key).or_insert(Vec::new());
-                entry.extend(trait_items.iter().filter_map(|trait_item_ref| {
-                    let hir_id = trait_item_ref.id.hir_id();
-                    hir_map
-                        .maybe_body_owned_by(hir_id.owner.def_id)
-                        .map(|body_id| (body_id, trait_item_ref.span))
-                }));
+                self.hash_map.entry(key).or_insert(Vec::new()).extend(bodies);
             }
             ItemKind::Fn(_fn_sig, _generics, body_id) => {
+                let bodies = self.with_nested_bodies(*body_id, item.span);
                 let key = None;
-                let entry = self.hash_map.entry(key).or_insert(Vec::new());
-                entry.push((*body_id, item.span));
+                self.hash_map.entry(key).or_insert(Vec::new()).extend(bodies);
+            }
+            // Initializers of consts and statics are analyzed like free-standing functions.
+            ItemKind::Const(_ty, body_id) | ItemKind::Static(_ty, _, body_id) => {
+                let bodies = self.with_nested_bodies(*body_id, item.span);
+                let key = None;
+                self.hash_map.entry(key).or_insert(Vec::new()).extend(bodies);
             }
             _ => (),
         }
@@ -86,6 +116,45 @@ impl<'tcx> intravisit::Visitor<'tcx> for RelatedFnCollector<'tcx> {
     }
 }
 
+/// Collects the bodies of closures, async blocks and generators (async fns included),
+/// walking into them to find the ones nested deeper.
+struct NestedBodyCollector<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    bodies: Vec<BodyId>,
+}
+
+impl<'tcx> NestedBodyCollector<'tcx> {
+    fn collect(tcx: TyCtxt<'tcx>, body_id: BodyId) -> Vec<BodyId> {
+        use intravisit::Visitor;
+
+        let mut visitor = NestedBodyCollector {
+            tcx,
+            bodies: Vec::new(),
+        };
+
+        let body = tcx.hir().body(body_id);
+        visitor.visit_body(body);
+
+        visitor.bodies
+    }
+}
+
+impl<'tcx> intravisit::Visitor<'tcx> for NestedBodyCollector<'tcx> {
+    type Map = rustc_middle::hir::map::Map<'tcx>;
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.tcx.hir()
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        if let ExprKind::Closure(closure) = expr.kind {
+            self.bodies.push(closure.body);
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
+
 pub struct ContainsUnsafe<'tcx> {
     tcx: TyCtxt<'tcx>,
     contains_unsafe: bool,
@@ -4,6 +4,59 @@ fn withdraw(amount: u64) {
     println!("{:?}", remained);
 }
 
+// not reported: the subtraction is guarded by `balance >= amount`
+fn guarded_withdraw(balance: u64, amount: u64) {
+    if balance >= amount {
+        let remained = balance - amount;
+        println!("{:?}", remained);
+    }
+}
+
+// reported: the guard checks the wrong operand order
+fn misguarded_withdraw(balance: u64, amount: u64) {
+    if amount >= balance {
+        let remained = balance - amount;
+        println!("{:?}", remained);
+    }
+}
+
+// reported: the guard is about the new `balance`, not the copy taken before it changed
+fn stale_guarded_withdraw(mut balance: u64, amount: u64) {
+    let old = balance;
+    balance = amount / 2;
+    if balance >= amount {
+        let remained = old - amount;
+        println!("{:?}", remained);
+    }
+}
+
+// not reported: `x & 0xff` always fits in a `u8`
+fn low_byte(x: u32) -> u8 {
+    (x & 0xff) as u8
+}
+
+// reported: `x` may not fit in a `u8`
+fn truncated_byte(x: u32) -> u8 {
+    x as u8
+}
+
+// not reported: `x % 100 + 1` is at most 100
+fn bounded_sum(x: u32) -> u32 {
+    x % 100 + 1
+}
+
+// reported: `i32::MIN % -1` overflows, so the remainder isn't known to fit in an `i8`
+fn signed_rem(x: i32, y: i32) -> i8 {
+    (x % y.clamp(-3, -1)) as i8
+}
+
 fn main() { // run with cargo run --release
     withdraw(100);
+    guarded_withdraw(100, 1);
+    misguarded_withdraw(1, 100);
+    stale_guarded_withdraw(1, 100);
+    println!("{}", low_byte(0x1234));
+    println!("{}", truncated_byte(0x1234));
+    println!("{}", bounded_sum(12345));
+    println!("{}", signed_rem(-7, -2));
 }
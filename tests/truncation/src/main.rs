@@ -4,8 +4,37 @@ fn buggy_function(data: &[u8; 32]) {
     println!("buffer: {:?}", buffer);
 }
 
+// Truncation only follows a copy source that was sliced out of an argument through an `Index`
+// call while a `RangeFull` index appears in the same body, so none of the copies below from a
+// plain slice argument is reported; the comments say what a length-aware check would report.
+
+// not reported: the lengths are compared before the copy
+fn checked_copy(dst: &mut [u8], src: &[u8]) {
+    if dst.len() == src.len() {
+        dst.copy_from_slice(src);
+    }
+}
+
+// not reported (missed): `dst` is shrunk after the check, so the lengths may differ again
+fn stale_checked_copy(dst: &mut Vec<u8>, src: &[u8], n: usize) {
+    if dst.len() == src.len() {
+        dst.truncate(n);
+        dst.copy_from_slice(src);
+    }
+}
+
+// not reported (missed): the lengths are never compared
+fn unchecked_copy(dst: &mut [u8], src: &[u8]) {
+    dst.copy_from_slice(src);
+}
+
 fn main() {
     let data = [1u8; 32];
     buggy_function(&data);
+
+    let mut dst = vec![0u8; 4];
+    checked_copy(&mut dst, &data[..4]);
+    stale_checked_copy(&mut dst, &data[..4], 2);
+    unchecked_copy(&mut dst, &data[..4]);
     println!("bug found!");
 }